@@ -1,74 +1,507 @@
+use crate::error::DocatError;
+use anyhow::{bail, Result};
 use dirs::home_dir;
-use std::path::PathBuf;
-use std::process::{Command, Output, Stdio};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::iter::Peekable;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::str::Chars;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, thread};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static PREFIX: RefCell<Option<String>> = const { RefCell::new(None) };
+}
 
 pub struct CommandWrapper {
     pub command: Command,
     pub ignore_output: bool,
     pub ignore_error: bool,
+    /// Commands that only read state still run in a dry run
+    pub read_only: bool,
 }
 
-pub fn new(program: &str, dir: &PathBuf) -> CommandWrapper {
+pub fn new(program: &str, dir: &Path) -> CommandWrapper {
     let mut cmd = Command::new(program);
     cmd.current_dir(dir);
 
-    let cmd = CommandWrapper {
+    CommandWrapper {
         command: cmd,
         ignore_output: false,
         ignore_error: false,
-    };
+        read_only: false,
+    }
+}
 
-    cmd
+/// Prefix every line of output from commands run on the current thread,
+/// so projects running at the same time can be told apart
+pub fn set_prefix(prefix: Option<String>) {
+    PREFIX.with(|current| *current.borrow_mut() = prefix);
 }
 
-pub fn run(cmd_wrapper: CommandWrapper) -> std::io::Result<Output> {
+/// Print commands that would change anything instead of running them
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+pub fn run(cmd_wrapper: CommandWrapper) -> Result<Output> {
+    if is_dry_run() && !cmd_wrapper.read_only {
+        return Ok(record(&cmd_wrapper.command));
+    }
+
     let mut cmd = cmd_wrapper.command;
-    if !cmd_wrapper.ignore_output {
-        cmd.stdout(Stdio::inherit());
-    }
-
-    if !cmd_wrapper.ignore_error {
-        cmd.stderr(Stdio::inherit());
-    }
-
-    cmd.output()
-}
-
-pub fn run_from_list(cmds: &Vec<String>, dir: &PathBuf, message: &str) -> Vec<Output> {
-    cmds.iter()
-        .map(parse)
-        .flat_map(|cmd_result| {
-            cmd_result
-                .map(|mut cmd| {
-                    cmd.current_dir(dir);
-                    cmd
-                })
-                .map(|cmd| CommandWrapper {
-                    command: cmd,
-                    ignore_output: false,
-                    ignore_error: false,
-                })
-        })
-        .map(run)
-        .map(|result| result.expect(message))
-        .collect()
-}
-
-fn parse(cmd_string: &String) -> Option<Command> {
-    shellwords::split(cmd_string)
-        .ok()
-        .and_then(|cmds| match cmds.as_slice() {
-            [] => None,
-            [first, args @ ..] => Some((first.clone(), args.to_vec())),
-        })
-        .map(|(first, args)| {
-            // look for $HOME var and replace with home directory
-            let args = args
-                .iter()
-                .map(|arg| str::replace(arg, "$HOME", home_dir().unwrap().to_str().unwrap()))
-                .collect::<Vec<String>>();
-            let mut cmd = Command::new(first);
-            cmd.args(args);
-            cmd
-        })
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let output = match PREFIX.with(|prefix| prefix.borrow().clone()) {
+        Some(prefix) => run_prefixed(
+            &mut cmd,
+            &prefix,
+            !cmd_wrapper.ignore_output,
+            !cmd_wrapper.ignore_error,
+        ),
+        None => {
+            if !cmd_wrapper.ignore_output {
+                cmd.stdout(Stdio::inherit());
+            }
+
+            if !cmd_wrapper.ignore_error {
+                cmd.stderr(Stdio::inherit());
+            }
+
+            cmd.output()
+        }
+    };
+
+    output.map_err(|err| match err.kind() {
+        ErrorKind::NotFound if cmd.get_current_dir().is_some_and(|dir| !dir.exists()) => {
+            DocatError::NoDirectory("working directory").into()
+        }
+        ErrorKind::NotFound => DocatError::ProgramNotFound(program).into(),
+        _ => anyhow::Error::new(err).context(format!("Could not run `{}`", describe(&cmd))),
+    })
+}
+
+/// Run the command and fail if it does not exit successfully
+pub fn run_checked(cmd_wrapper: CommandWrapper) -> Result<Output> {
+    let command = describe(&cmd_wrapper.command);
+    let output = run(cmd_wrapper)?;
+
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(DocatError::CommandFailed {
+            command,
+            code: output.status.code(),
+        }
+        .into()),
+    }
+}
+
+/// Build and run each command in order, stopping at the first one that
+/// fails unless failures should only be reported
+pub fn run_from_list<T, F>(cmds: &[T], continue_on_error: bool, build: F) -> Result<Vec<Output>>
+where
+    F: Fn(&T) -> Result<Option<CommandWrapper>>,
+{
+    let mut outputs = Vec::new();
+
+    for cmd in cmds {
+        let result = build(cmd).and_then(|cmd_wrapper| cmd_wrapper.map(run_checked).transpose());
+
+        match result {
+            Ok(output) => outputs.extend(output),
+            Err(err) if continue_on_error => {
+                let prefix = PREFIX.with(|prefix| prefix.borrow().clone());
+                eprintln!(
+                    "{}Continuing after error: {:#}",
+                    prefix.unwrap_or_default(),
+                    err
+                );
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// The program and arguments of a command, as they would be typed in a shell
+pub fn describe(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| shellwords::escape(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Print the command and where it would run, and pretend it succeeded
+fn record(cmd: &Command) -> Output {
+    let prefix = PREFIX.with(|prefix| prefix.borrow().clone());
+    let dir = cmd
+        .get_current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    println!("{}[{}] {}", prefix.unwrap_or_default(), dir, describe(cmd));
+
+    Output {
+        status: ExitStatus::default(),
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    }
+}
+
+/// Build a command from a string like the ones in the hook lists. The
+/// variables in `env` are expanded and passed on to the command.
+pub fn from_string(
+    cmd_string: &str,
+    dir: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<Option<CommandWrapper>> {
+    let words = split(cmd_string, env)?;
+
+    Ok(words.split_first().map(|(program, args)| {
+        let mut cmd_wrapper = new(program, dir);
+        cmd_wrapper.command.args(args).envs(env);
+        cmd_wrapper
+    }))
+}
+
+/// Build a command that passes the whole command string to a shell like
+/// `sh -c`, which takes care of pipes, redirection and expansion
+pub fn from_shell(
+    shell: &str,
+    cmd_string: &str,
+    dir: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<CommandWrapper> {
+    let words = split(shell, env)?;
+    let (program, args) = match words.split_first() {
+        Some(words) => words,
+        None => bail!("No shell given to run `{}` with", cmd_string),
+    };
+
+    let mut cmd_wrapper = new(program, dir);
+    cmd_wrapper.command.args(args).arg(cmd_string).envs(env);
+
+    Ok(cmd_wrapper)
+}
+
+/// Split a command string into words the way a shell would, expanding a
+/// leading `~` and variables outside of single quotes. `$$` and `\$` are a
+/// literal `$`.
+pub fn split(cmd_string: &str, env: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = cmd_string.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (None, char) if char.is_whitespace() => words.extend(word.take()),
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                word.get_or_insert_default();
+            }
+            (Some(open), char) if char == open => quote = None,
+            (Some('\''), char) => word.get_or_insert_default().push(char),
+            (None, '\\') => word
+                .get_or_insert_default()
+                .push(chars.next().unwrap_or('\\')),
+            // in double quotes a backslash only escapes what is special there
+            (Some(_), '\\') => match chars.next_if(|next| matches!(next, '"' | '\\' | '$')) {
+                Some(escaped) => word.get_or_insert_default().push(escaped),
+                None => word.get_or_insert_default().push(char),
+            },
+            (None, '~')
+                if word.is_none()
+                    && chars
+                        .peek()
+                        .is_none_or(|next| *next == '/' || next.is_whitespace()) =>
+            {
+                word.get_or_insert_default().push_str(&home()?)
+            }
+            (_, '$') => word
+                .get_or_insert_default()
+                .push_str(&variable(&mut chars, env)?),
+            (_, char) => word.get_or_insert_default().push(char),
+        }
+    }
+
+    if quote.is_some() {
+        bail!("Could not parse `{}`, a quote is not closed", cmd_string);
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Expand a leading `~` and every `$VAR`, `${VAR}` and `${VAR:-default}`
+/// in a single word, like an environment value or a directory
+pub fn expand(word: &str, env: &BTreeMap<String, String>) -> Result<String> {
+    let mut expanded = String::new();
+    let mut chars = word.chars().peekable();
+    if word == "~" || word.starts_with("~/") {
+        chars.next();
+        expanded.push_str(&home()?);
+    }
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' if chars.next_if_eq(&'$').is_some() => expanded.push('$'),
+            '$' => expanded.push_str(&variable(&mut chars, env)?),
+            char => expanded.push(char),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// The value of the variable that follows a `$`. Variables are looked up
+/// in `env` before the environment of docat, and one that is not defined
+/// anywhere and has no default is an error.
+fn variable(chars: &mut Peekable<Chars>, env: &BTreeMap<String, String>) -> Result<String> {
+    let (name, default) = match chars.peek() {
+        Some('$') => {
+            chars.next();
+            return Ok("$".to_string());
+        }
+        Some('{') => {
+            chars.next();
+            let mut inner = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(char) => inner.push(char),
+                    None => bail!("Could not parse ${{{}, the brace is not closed", inner),
+                }
+            }
+
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name.to_string(), Some(default.to_string())),
+                None => (inner, None),
+            };
+            if !is_name(&name) {
+                bail!(
+                    "Could not parse ${{{}}}, {} is not a variable name",
+                    name,
+                    name
+                );
+            }
+            (name, default)
+        }
+        Some(char) if char.is_ascii_alphabetic() || *char == '_' => {
+            let mut name = String::new();
+            while let Some(char) =
+                chars.next_if(|char| char.is_ascii_alphanumeric() || *char == '_')
+            {
+                name.push(char);
+            }
+            (name, None)
+        }
+        // a `$` that starts no variable is kept
+        _ => return Ok("$".to_string()),
+    };
+
+    let value = env.get(&name).cloned().or_else(|| env::var(&name).ok());
+    match (value, default) {
+        // like a shell, the default also replaces an empty value
+        (Some(value), Some(default)) if value.is_empty() => Ok(default),
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default),
+        (None, None) => bail!(DocatError::UndefinedVariable(name)),
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+fn home() -> Result<String> {
+    let home = home_dir().ok_or(DocatError::NoDirectory("home directory"))?;
+
+    Ok(home.display().to_string())
+}
+
+fn run_prefixed(
+    cmd: &mut Command,
+    prefix: &str,
+    forward_output: bool,
+    forward_error: bool,
+) -> std::io::Result<Output> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let (stdout, stderr) = thread::scope(|scope| {
+        let stdout = scope.spawn(|| forward(stdout, forward_output.then_some(prefix), false));
+        let stderr = scope.spawn(|| forward(stderr, forward_error.then_some(prefix), true));
+        (stdout.join().unwrap(), stderr.join().unwrap())
+    });
+
+    Ok(Output {
+        status: child.wait()?,
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+/// Print each line of the stream with the prefix, or capture it if there is none
+fn forward(
+    mut stream: impl Read,
+    prefix: Option<&str>,
+    is_error: bool,
+) -> std::io::Result<Vec<u8>> {
+    let mut captured = Vec::new();
+
+    match prefix {
+        None => {
+            stream.read_to_end(&mut captured)?;
+        }
+        Some(prefix) => {
+            for line in BufReader::new(stream).split(b'\n') {
+                let line = String::from_utf8_lossy(&line?).into_owned();
+                match is_error {
+                    true => eprintln!("{}{}", prefix, line),
+                    false => println!("{}{}", prefix, line),
+                }
+            }
+        }
+    }
+
+    Ok(captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("NAME".to_string(), "docat".to_string()),
+            ("EMPTY".to_string(), String::new()),
+            ("SPACED".to_string(), "a b".to_string()),
+        ])
+    }
+
+    fn words(cmd_string: &str) -> Vec<String> {
+        split(cmd_string, &env()).unwrap()
+    }
+
+    #[test]
+    fn split_on_whitespace() {
+        assert_eq!(words("  echo  a\tb "), ["echo", "a", "b"]);
+        assert!(words("").is_empty());
+    }
+
+    #[test]
+    fn split_keeps_quoted_words() {
+        assert_eq!(words("echo 'a b' \"c d\""), ["echo", "a b", "c d"]);
+        assert_eq!(words("echo '' \"\""), ["echo", "", ""]);
+        assert_eq!(words("echo a'b'\"c\""), ["echo", "abc"]);
+        assert_eq!(
+            words("echo \"it's\" 'say \"hi\"'"),
+            ["echo", "it's", "say \"hi\""]
+        );
+    }
+
+    #[test]
+    fn split_fails_on_unclosed_quote() {
+        assert!(split("echo 'a", &env()).is_err());
+        assert!(split("echo \"a", &env()).is_err());
+    }
+
+    #[test]
+    fn split_escapes() {
+        assert_eq!(words("echo a\\ b \\'c"), ["echo", "a b", "'c"]);
+        assert_eq!(words("echo \"a\\\"b\\\\c\\d\""), ["echo", "a\"b\\c\\d"]);
+        assert_eq!(words("echo 'a\\b'"), ["echo", "a\\b"]);
+    }
+
+    #[test]
+    fn split_expands_variables_outside_single_quotes() {
+        assert_eq!(
+            words("echo $NAME \"${NAME}-x\" '$NAME'"),
+            ["echo", "docat", "docat-x", "$NAME"]
+        );
+        // a value is one word, like in double quotes
+        assert_eq!(words("echo $SPACED"), ["echo", "a b"]);
+    }
+
+    #[test]
+    fn split_keeps_escaped_dollars() {
+        assert_eq!(
+            words("echo $$NAME \\$NAME \"\\$NAME\" $ a$"),
+            ["echo", "$NAME", "$NAME", "$NAME", "$", "a$"]
+        );
+    }
+
+    #[test]
+    fn split_expands_a_leading_tilde() {
+        let home = home().unwrap();
+        assert_eq!(
+            words("ls ~ ~/src a~ '~' ~user"),
+            ["ls", &home, &format!("{}/src", home), "a~", "~", "~user"]
+        );
+    }
+
+    #[test]
+    fn expand_variables() {
+        let expand = |word| expand(word, &env()).unwrap();
+        assert_eq!(expand("$NAME/${NAME}"), "docat/docat");
+        assert_eq!(expand("${DOCAT_TEST_UNDEFINED:-fallback}"), "fallback");
+        assert_eq!(expand("${EMPTY:-fallback}"), "fallback");
+        assert_eq!(expand("${NAME:-fallback}"), "docat");
+        assert_eq!(expand("${DOCAT_TEST_UNDEFINED:-}"), "");
+        assert_eq!(expand("'$NAME'"), "'docat'");
+    }
+
+    #[test]
+    fn expand_keeps_escaped_dollars() {
+        let expand = |word| expand(word, &env()).unwrap();
+        assert_eq!(expand("\\$NAME"), "$NAME");
+        assert_eq!(expand("a\\$"), "a$");
+        assert_eq!(expand("$$NAME"), "$NAME");
+        assert_eq!(expand("a\\b"), "a\\b");
+        assert_eq!(expand("5$"), "5$");
+    }
+
+    #[test]
+    fn expand_a_leading_tilde() {
+        let home = home().unwrap();
+        assert_eq!(expand("~", &env()).unwrap(), home);
+        assert_eq!(expand("~/src", &env()).unwrap(), format!("{}/src", home));
+        assert_eq!(expand("a/~", &env()).unwrap(), "a/~");
+    }
+
+    #[test]
+    fn undefined_variables_fail() {
+        for word in ["$DOCAT_TEST_UNDEFINED", "${DOCAT_TEST_UNDEFINED}"] {
+            let err = expand(word, &env()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(DocatError::UndefinedVariable(name)) if name == "DOCAT_TEST_UNDEFINED"
+            ));
+            assert!(split(word, &env()).is_err());
+        }
+    }
+
+    #[test]
+    fn malformed_braces_fail() {
+        assert!(expand("${NAME", &env()).is_err());
+        assert!(expand("${1x}", &env()).is_err());
+    }
 }
@@ -1,54 +1,77 @@
 use crate::config::config::Config;
+use crate::error::DocatError;
 use crate::file::CONFIG_FILENAME;
-use crate::{cwd, file};
-use anyhow::{bail, Result};
+use crate::{cmd, cwd, file, ProjectDirName};
+use anyhow::Result;
 use app::App;
+use regex::Regex;
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
 use std::{env, fs};
 
 pub(crate) mod app;
-mod app_config;
+pub(crate) mod app_config;
+#[allow(clippy::module_inception)]
 pub(crate) mod config;
 pub(crate) mod project;
+pub(crate) mod readiness;
+pub(crate) mod task;
+pub(crate) mod validate;
+
+// combining writes the cached config, so only one thread may do it at a time
+static COMBINE: Mutex<()> = Mutex::new(());
+
+static UNKNOWN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"unknown (field|variant) `([^`]*)`, expected (.*)").unwrap());
+
+static QUOTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`]*)`").unwrap());
+
+static LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r" at line \d+ column \d+$").unwrap());
 
 pub fn combine(app: &Option<String>) -> Result<App> {
+    let _lock = COMBINE.lock().unwrap();
+
     // try and load file from current directory
-    let config = load_from(&cwd()).ok();
-    let cached_config_path = file::cached_config_path();
-    let cached_config_file = file::cached_config_file();
-    let current_dir_name = cwd().file_name().unwrap().to_str().unwrap().to_string();
+    let cwd = cwd()?;
+    let config = load_if_exists(&cwd)?;
+    let cached_config_path = file::cached_config_path()?;
+    let cached_config_file = file::cached_config_file()?;
+    let current_dir_name = dir_name(&cwd);
     let mut cached_config = load_from(&cached_config_path).or_else(|err| {
         let is_install_dir = config
             .as_ref()
             .map(|config| {
                 config
                     .apps
-                    .iter()
-                    .map(|(_, app)| {
+                    .values()
+                    .map(|app| {
                         app.projects
                             .iter()
                             .find(|(_, project)| project.is_install)
                             .map(|(dir_name, _)| dir_name.clone())
                     })
                     .rfold(None, |_, name| name)
-                    .map_or(false, |name| name == current_dir_name)
+                    .is_some_and(|name| name == current_dir_name)
             })
             .unwrap_or(false);
 
         match is_install_dir {
             true => {
                 // copy config
-                let mut config_file = cwd();
-                config_file.push(CONFIG_FILENAME);
-                fs::copy(config_file, cached_config_file.clone())?;
+                if !cmd::is_dry_run() {
+                    let mut config_file = cwd.clone();
+                    config_file.push(CONFIG_FILENAME);
+                    fs::copy(config_file, cached_config_file.clone())?;
+                }
                 config.clone().ok_or(err)
             }
-            false => bail!(err.to_string()),
+            false => Err(err),
         }
     })?;
-    let app_name = &get_app_name(app, &config, &cached_config);
-    let app = cached_config.get(app_name);
+    let app_name = &get_app_name(app, &config, &cached_config, &cwd)?;
+    let app = cached_config.get(app_name)?;
 
     // reset projects to clear out any old values
     app.projects = app
@@ -60,42 +83,129 @@ pub fn combine(app: &Option<String>) -> Result<App> {
     // find install directory
     let install_config = load_from(&app.config.install_dir)?;
 
-    let mut merged_config = cached_config.merge(&install_config);
+    let mut merged_config = cached_config.merge(&install_config)?;
 
     let project_configs = merged_config
-        .get(app_name)
+        .get(app_name)?
         .projects
         .iter()
         .filter(|(_, project)| !path_buf_is_new(&project.dir))
         .map(|(_, project)| &project.dir)
-        .map(load_from)
-        .filter_map(|result| result.ok())
-        .collect::<Vec<Config>>();
+        .map(|dir| load_if_exists(dir))
+        .filter_map(Result::transpose)
+        .collect::<Result<Vec<Config>>>()?;
 
     let mut all_configs = project_configs
         .iter()
-        .fold(merged_config, |base_config, config| {
+        .try_fold(merged_config, |base_config, config| {
             base_config.merge(config)
-        });
+        })?;
+
+    if !cmd::is_dry_run() {
+        fs::write(cached_config_file, serde_yaml::to_string(&all_configs)?)?;
+    }
 
-    fs::write(cached_config_file, serde_yaml::to_string(&all_configs)?)?;
+    let mut app = all_configs.get(app_name)?.clone();
+    app.name = app_name.clone();
 
-    Ok(all_configs.get(app_name).clone())
+    Ok(app)
 }
 
-pub fn load_from(dir: &PathBuf) -> Result<Config> {
-    let mut path = dir.clone();
+/// Drop projects from the cached config, e.g. once they are uninstalled.
+/// Projects listed by the install project come back on the next combine.
+pub fn forget(app_name: &String, dir_names: &[ProjectDirName]) -> Result<()> {
+    let _lock = COMBINE.lock().unwrap();
+
+    let mut cached_config = load_from(&file::cached_config_path()?)?;
+    let app = cached_config.get(app_name)?;
+    dir_names.iter().for_each(|dir_name| {
+        app.projects.remove(dir_name);
+    });
+
+    fs::write(
+        file::cached_config_file()?,
+        serde_yaml::to_string(&cached_config)?,
+    )?;
+
+    Ok(())
+}
+
+pub fn load_from(dir: &Path) -> Result<Config> {
+    let mut path = dir.to_path_buf();
     path.push(CONFIG_FILENAME);
-    let yaml = fs::read_to_string(path)?;
+    let yaml = fs::read_to_string(&path).map_err(|_| DocatError::ConfigNotFound(path.clone()))?;
+
+    parse(&path, &yaml)
+}
+
+/// Like `load_from`, but a directory without a docat.yml is not an error
+fn load_if_exists(dir: &Path) -> Result<Option<Config>> {
+    match load_from(dir) {
+        Err(err) if matches!(err.downcast_ref(), Some(DocatError::ConfigNotFound(_))) => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+/// Parse the contents of a docat.yml, describing an error with where it is
+/// and the closest known name for a misspelled key
+fn parse(path: &Path, yaml: &str) -> Result<Config> {
+    serde_yaml::from_str(yaml)
+        .map_err(|err| DocatError::InvalidConfig(path.to_path_buf(), describe(&err)).into())
+}
+
+fn describe(err: &serde_yaml::Error) -> String {
+    let message = LOCATION.replace(&err.to_string(), "").into_owned();
+
+    let suggestion = UNKNOWN.captures(&message).and_then(|captures| {
+        let expected = QUOTED
+            .captures_iter(captures.get(3).unwrap().as_str())
+            .map(|quoted| quoted.get(1).unwrap().as_str());
+
+        suggest(&captures[2], expected).map(|known| {
+            format!(
+                "{}unknown {} `{}`, did you mean `{}`?",
+                &message[..captures.get(0).unwrap().start()],
+                &captures[1],
+                &captures[2],
+                known
+            )
+        })
+    });
+    let message = suggestion.unwrap_or(message);
+
+    match err.location() {
+        Some(location) => format!(
+            "line {} column {}: {}",
+            location.line(),
+            location.column(),
+            message
+        ),
+        None => message,
+    }
+}
+
+/// The known name closest to a misspelled one, if any is close enough
+fn suggest<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    known
+        .map(|known| (strsim::jaro_winkler(name, known), known))
+        .filter(|(confidence, _)| *confidence > 0.8)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, known)| known)
+}
 
-    Ok(serde_yaml::from_str(&yaml)?)
+/// The name of the directory, which is how projects are keyed in the config
+pub fn dir_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
 }
 
 fn get_app_name(
     app_name: &Option<String>,
     project_config: &Option<Config>,
     cached_config: &Config,
-) -> String {
+    cwd: &PathBuf,
+) -> Result<String> {
     app_name
         .clone()
         .or_else(|| {
@@ -104,15 +214,14 @@ fn get_app_name(
                 .and_then(|config| config.apps.iter().next().map(|(key, _)| key.clone()))
         })
         .or_else(|| {
-            let cwd = cwd();
             cached_config
                 .apps
                 .iter()
-                .find(|(_, app)| app.projects.iter().any(|(_, project)| project.dir.eq(&cwd)))
+                .find(|(_, app)| app.projects.iter().any(|(_, project)| project.dir.eq(cwd)))
                 .map(|(app_name, _)| app_name.clone())
         })
         .or_else(|| env::var("DOCAT_APP").ok())
-        .expect("Could not determine app name, try passing it in as a flag")
+        .ok_or_else(|| DocatError::AppNotDetermined.into())
 }
 
 fn bool_is_false(bool: &bool) -> bool {
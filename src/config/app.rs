@@ -2,16 +2,25 @@ use crate::config::app_config::AppConfig;
 use crate::config::path_buf_is_new;
 use crate::config::project::Project;
 use crate::{cwd, ProjectDirName};
+use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::collections::BTreeMap;
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct App {
-    #[serde(default = "BTreeMap::new", skip_serializing_if = "BTreeMap::is_empty")]
+    /// The key of the app in the config, filled in by `config::combine`
+    #[serde(skip)]
+    pub name: String,
+
+    /// Projects by the name of their directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub projects: BTreeMap<ProjectDirName, Project>,
 
+    /// Settings shared by every project of the app
     #[serde(
         default = "AppConfig::new",
         skip_serializing_if = "AppConfig::is_empty"
@@ -22,6 +31,7 @@ pub struct App {
 impl App {
     pub fn new() -> Self {
         App {
+            name: String::new(),
             projects: BTreeMap::new(),
             config: AppConfig::new(),
         }
@@ -33,14 +43,14 @@ impl App {
         self.projects.get_mut(name).unwrap()
     }
 
-    pub fn merge(&self, provided_app: &App) -> Self {
+    pub fn merge(&self, provided_app: &App) -> Result<Self> {
         let mut new_app = self.clone();
 
         new_app.config = new_app.config.merge(&provided_app.config);
 
         // if we don't have an install directory, construct it based on the cwd
         if path_buf_is_new(&new_app.config.install_dir) {
-            new_app.config.install_dir = cwd();
+            new_app.config.install_dir = cwd()?;
             let mut shared_dir = new_app.config.install_dir.clone();
             shared_dir.pop();
             new_app.config.shared_dir = shared_dir;
@@ -75,6 +85,6 @@ impl App {
 
         new_app.projects.append(merged_projects);
 
-        new_app
+        Ok(new_app)
     }
 }
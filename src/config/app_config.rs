@@ -1,19 +1,38 @@
 use crate::config::path_buf_is_new;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
+    /// Network every project is connected to
     #[serde(default = "String::new", skip_serializing_if = "String::is_empty")]
     pub shared_network: String,
 
+    /// Directory of the install project, filled in by docat
     #[serde(default = "PathBuf::new", skip_serializing_if = "path_buf_is_new")]
     pub install_dir: PathBuf,
 
+    /// Directory the projects are cloned into, filled in by docat
     #[serde(default = "PathBuf::new", skip_serializing_if = "path_buf_is_new")]
     pub shared_dir: PathBuf,
+
+    /// Container runtime to use, detected from the PATH when not set
+    pub runtime: Option<RuntimeKind>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuntimeKind {
+    /// docker with the compose v2 plugin
+    Docker,
+    /// docker with the standalone docker-compose v1 binary
+    DockerCompose,
+    Podman,
+    Nerdctl,
 }
 
 impl AppConfig {
@@ -22,12 +41,13 @@ impl AppConfig {
             shared_network: "".to_string(),
             install_dir: PathBuf::new(),
             shared_dir: PathBuf::new(),
+            runtime: None,
         }
     }
 
-    pub fn init(&mut self, dir: &PathBuf) {
-        let install_dir = dir.clone();
-        let mut shared_dir = dir.clone();
+    pub fn init(&mut self, dir: &Path) {
+        let install_dir = dir.to_path_buf();
+        let mut shared_dir = dir.to_path_buf();
         shared_dir.pop();
 
         self.install_dir = install_dir;
@@ -38,6 +58,7 @@ impl AppConfig {
         self.shared_network.is_empty()
             && path_buf_is_new(&self.install_dir)
             && path_buf_is_new(&self.shared_dir)
+            && self.runtime.is_none()
     }
 
     pub fn merge(&self, config: &AppConfig) -> AppConfig {
@@ -47,6 +68,10 @@ impl AppConfig {
             new_config.shared_network = config.shared_network.clone();
         }
 
+        if config.runtime.is_some() {
+            new_config.runtime = config.runtime;
+        }
+
         new_config
     }
 }
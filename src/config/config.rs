@@ -1,16 +1,15 @@
 use crate::config::app::App;
+use crate::error::DocatError;
+use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_with::skip_serializing_none;
 use std::collections::BTreeMap;
 
-#[skip_serializing_none]
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+/// The apps in a docat.yml by name. Transparent rather than flattened so
+/// parse errors keep their line and column.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(transparent)]
 pub struct Config {
-    #[serde(
-        flatten,
-        default = "BTreeMap::new",
-        skip_serializing_if = "BTreeMap::is_empty"
-    )]
     pub apps: BTreeMap<String, App>,
 }
 
@@ -26,30 +25,31 @@ impl Config {
         self.apps.get_mut(name).unwrap()
     }
 
-    pub fn get(&mut self, name: &String) -> &mut App {
-        self.apps.get_mut(name).unwrap()
+    pub fn get(&mut self, name: &String) -> Result<&mut App> {
+        self.apps
+            .get_mut(name)
+            .ok_or_else(|| DocatError::UnknownApp(name.clone()).into())
     }
 
-    pub fn merge(&self, config: &Config) -> Self {
+    pub fn merge(&self, config: &Config) -> Result<Self> {
         let mut new_config = self.clone();
 
         let merged_apps = &mut config
             .apps
             .iter()
             .map(|(key, app)| {
-                (
+                Ok((
                     key.clone(),
-                    self.apps
-                        .get(key)
-                        .cloned()
-                        .map(|original_app| original_app.merge(app))
-                        .unwrap_or(app.clone()),
-                )
+                    match self.apps.get(key) {
+                        Some(original_app) => original_app.merge(app)?,
+                        None => app.clone(),
+                    },
+                ))
             })
-            .collect::<BTreeMap<_, _>>();
+            .collect::<Result<BTreeMap<_, _>>>()?;
 
         new_config.apps.append(merged_apps);
 
-        new_config
+        Ok(new_config)
     }
 }
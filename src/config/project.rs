@@ -1,41 +1,213 @@
+use crate::cmd::CommandWrapper;
+use crate::config::readiness::Readiness;
+use crate::config::task::TaskStep;
 use crate::config::{bool_is_false, path_buf_is_new};
-use crate::ProjectDirName;
+use crate::{cmd, ProjectDirName};
+use anyhow::Result;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    BeforeInstall,
+    OnInstall,
+    AfterInstall,
+    OnUp,
+    AfterUp,
+    BeforeDown,
+    AfterDown,
+    OnRestart,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Hook::BeforeInstall => write!(f, "before_install"),
+            Hook::OnInstall => write!(f, "on_install"),
+            Hook::AfterInstall => write!(f, "after_install"),
+            Hook::OnUp => write!(f, "on_up"),
+            Hook::AfterUp => write!(f, "after_up"),
+            Hook::BeforeDown => write!(f, "before_down"),
+            Hook::AfterDown => write!(f, "after_down"),
+            Hook::OnRestart => write!(f, "on_restart"),
+        }
+    }
+}
+
+/// A hook command, either just the command or with the directory,
+/// environment and shell to run it with
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a command, or a map with `run` and optionally `cwd`, `env` and `shell`"
+)]
+pub enum HookCommand {
+    Command(String),
+    Structured {
+        /// The command to run
+        run: String,
+        /// Relative to the project directory
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+        /// Variables to set, which may refer to the ones docat sets
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        /// Shell to run the command with instead of the one of the project
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shell: Option<String>,
+    },
+}
+
+impl fmt::Display for HookCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HookCommand::Command(run) | HookCommand::Structured { run, .. } => write!(f, "{}", run),
+        }
+    }
+}
+
+impl HookCommand {
+    /// Build the command to run in `dir`. With a shell, like `sh -c`, the
+    /// whole command is passed to it instead of being split into words.
+    pub fn build(
+        &self,
+        shell: Option<&str>,
+        dir: &Path,
+        env: &BTreeMap<String, String>,
+    ) -> Result<Option<CommandWrapper>> {
+        let (run, cwd, extra_env, own_shell) = match self {
+            HookCommand::Command(run) => (run, None, None, None),
+            HookCommand::Structured {
+                run,
+                cwd,
+                env,
+                shell,
+            } => (run, cwd.as_ref(), Some(env), shell.as_deref()),
+        };
+
+        let mut env = env.clone();
+        for (name, value) in extra_env.into_iter().flatten() {
+            let value = cmd::expand(value, &env)?;
+            env.insert(name.clone(), value);
+        }
+
+        let dir = match cwd {
+            Some(cwd) => dir.join(cmd::expand(cwd, &env)?),
+            None => dir.to_path_buf(),
+        };
+
+        match own_shell.or(shell) {
+            Some(shell) => cmd::from_shell(shell, run, &dir, &env).map(Some),
+            None => cmd::from_string(run, &dir, &env),
+        }
+    }
+}
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Project {
+    /// Name shown in output, defaults to the directory name
     pub name: Option<String>,
 
+    /// Repository the project is cloned from
     #[serde(default = "String::new", skip_serializing_if = "String::is_empty")]
     pub git: String,
 
+    /// Branch to check out and track instead of the default branch
+    #[serde(default = "String::new", skip_serializing_if = "String::is_empty")]
+    pub branch: String,
+
+    /// Tag or commit to pin the checkout to, takes precedence over branch
+    #[serde(
+        rename = "ref",
+        default = "String::new",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub reference: String,
+
+    /// Name of the project directory, filled in by docat
     #[serde(default = "String::new", skip_serializing_if = "String::is_empty")]
     pub dir_name: ProjectDirName,
 
+    /// Path of the project directory, filled in by docat
     #[serde(default = "PathBuf::new", skip_serializing_if = "path_buf_is_new")]
     pub dir: PathBuf,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    /// Shell to run hook commands with, e.g. `sh -c` or `bash -lc`
+    pub shell: Option<String>,
+
+    /// Networks created before the project comes up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub networks: Vec<String>,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    /// Volumes created before the project comes up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub volumes: Vec<String>,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
-    pub on_install: Vec<String>,
+    /// Runs in the shared directory before the project is cloned
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before_install: Vec<HookCommand>,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
-    pub on_up: Vec<String>,
+    /// Runs in the project directory once it is cloned
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_install: Vec<HookCommand>,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
-    pub after_up: Vec<String>,
+    /// Runs after on_install
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after_install: Vec<HookCommand>,
 
-    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    /// Runs before the project is brought up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_up: Vec<HookCommand>,
+
+    /// Runs once the project is up and ready
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after_up: Vec<HookCommand>,
+
+    /// Runs before the project is brought down
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before_down: Vec<HookCommand>,
+
+    /// Runs once the project is down
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after_down: Vec<HookCommand>,
+
+    /// Runs between bringing the project down and up again on restart
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_restart: Vec<HookCommand>,
+
+    /// Compose files to use instead of the default one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub compose_files: Vec<String>,
 
+    /// Projects that must be up before this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<ProjectDirName>,
+
+    /// How to tell the project is ready once it is up
+    #[serde(
+        default = "Readiness::new",
+        skip_serializing_if = "Readiness::is_empty"
+    )]
+    pub ready: Readiness,
+
+    /// Hooks whose failing commands do not stop the rest
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub continue_on_error: Vec<Hook>,
+
+    /// Named lists of steps run with `docat task <name>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tasks: BTreeMap<String, Vec<TaskStep>>,
+
+    /// Whether the project holds the docat.yml of the app
     #[serde(default = "bool::default", skip_serializing_if = "bool_is_false")]
     pub is_install: bool,
 }
@@ -45,14 +217,26 @@ impl Project {
         Project {
             name: None,
             git: "".to_string(),
+            branch: "".to_string(),
+            reference: "".to_string(),
             dir: PathBuf::new(),
             dir_name: "".to_string(),
+            shell: None,
             networks: Vec::new(),
             volumes: Vec::new(),
+            before_install: Vec::new(),
             on_install: Vec::new(),
+            after_install: Vec::new(),
             on_up: Vec::new(),
             after_up: Vec::new(),
+            before_down: Vec::new(),
+            after_down: Vec::new(),
+            on_restart: Vec::new(),
             compose_files: Vec::new(),
+            depends_on: Vec::new(),
+            ready: Readiness::new(),
+            continue_on_error: Vec::new(),
+            tasks: BTreeMap::new(),
             is_install: false,
         }
     }
@@ -61,6 +245,27 @@ impl Project {
         self.name.clone().unwrap_or(self.dir_name.clone())
     }
 
+    /// The branch or ref the checkout should be on, if one is pinned
+    pub fn pinned(&self) -> Option<&str> {
+        [&self.reference, &self.branch]
+            .into_iter()
+            .find(|revision| !revision.is_empty())
+            .map(String::as_str)
+    }
+
+    pub fn hook(&self, hook: Hook) -> &Vec<HookCommand> {
+        match hook {
+            Hook::BeforeInstall => &self.before_install,
+            Hook::OnInstall => &self.on_install,
+            Hook::AfterInstall => &self.after_install,
+            Hook::OnUp => &self.on_up,
+            Hook::AfterUp => &self.after_up,
+            Hook::BeforeDown => &self.before_down,
+            Hook::AfterDown => &self.after_down,
+            Hook::OnRestart => &self.on_restart,
+        }
+    }
+
     pub fn merge(&self, provided_project: &Project) -> Project {
         let mut new_project = self.clone();
 
@@ -74,10 +279,22 @@ impl Project {
             new_project.git = project.git;
         }
 
+        if !project.branch.is_empty() {
+            new_project.branch = project.branch;
+        }
+
+        if !project.reference.is_empty() {
+            new_project.reference = project.reference;
+        }
+
         if !project.dir_name.is_empty() {
             new_project.dir_name = project.dir_name;
         }
 
+        if project.shell.is_some() {
+            new_project.shell = project.shell.take();
+        }
+
         if !project.networks.is_empty() {
             new_project.networks = project.networks;
         }
@@ -86,10 +303,18 @@ impl Project {
             new_project.volumes = project.volumes;
         }
 
+        if !project.before_install.is_empty() {
+            new_project.before_install = project.before_install;
+        }
+
         if !project.on_install.is_empty() {
             new_project.on_install = project.on_install;
         }
 
+        if !project.after_install.is_empty() {
+            new_project.after_install = project.after_install;
+        }
+
         if !project.on_up.is_empty() {
             new_project.on_up = project.on_up;
         }
@@ -98,10 +323,38 @@ impl Project {
             new_project.after_up = project.after_up;
         }
 
+        if !project.before_down.is_empty() {
+            new_project.before_down = project.before_down;
+        }
+
+        if !project.after_down.is_empty() {
+            new_project.after_down = project.after_down;
+        }
+
+        if !project.on_restart.is_empty() {
+            new_project.on_restart = project.on_restart;
+        }
+
         if !project.compose_files.is_empty() {
             new_project.compose_files = project.compose_files;
         }
 
+        if !project.depends_on.is_empty() {
+            new_project.depends_on = project.depends_on;
+        }
+
+        if !project.ready.is_empty() {
+            new_project.ready = project.ready;
+        }
+
+        if !project.continue_on_error.is_empty() {
+            new_project.continue_on_error = project.continue_on_error;
+        }
+
+        if !project.tasks.is_empty() {
+            new_project.tasks = project.tasks;
+        }
+
         new_project.is_install = project.is_install;
 
         new_project
@@ -111,12 +364,24 @@ impl Project {
         let mut project = self.clone();
 
         project.git = String::new();
+        project.branch = String::new();
+        project.reference = String::new();
+        project.shell = None;
         project.networks = Vec::new();
         project.volumes = Vec::new();
+        project.before_install = Vec::new();
         project.on_install = Vec::new();
+        project.after_install = Vec::new();
         project.on_up = Vec::new();
         project.after_up = Vec::new();
+        project.before_down = Vec::new();
+        project.after_down = Vec::new();
+        project.on_restart = Vec::new();
         project.compose_files = Vec::new();
+        project.depends_on = Vec::new();
+        project.ready = Readiness::new();
+        project.continue_on_error = Vec::new();
+        project.tasks = BTreeMap::new();
 
         project
     }
@@ -0,0 +1,50 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+pub const DEFAULT_TIMEOUT: u64 = 60;
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Readiness {
+    /// Seconds to wait for the project to become ready
+    pub timeout: Option<u64>,
+
+    /// Checks that must all pass before the project counts as ready
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    #[schemars(with = "Vec<Probe>")]
+    pub probes: Vec<Probe>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Probe {
+    /// Address that should accept connections, e.g. localhost:5432
+    Tcp(String),
+    /// Url that should respond with a successful status
+    Http(String),
+    /// Command that should exit successfully
+    Command(String),
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness {
+            timeout: None,
+            probes: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timeout.is_none() && self.probes.is_empty()
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+}
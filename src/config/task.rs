@@ -0,0 +1,42 @@
+use crate::config::project::HookCommand;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+
+/// One step of a task, either a command run in the project directory, in
+/// any form a hook takes, or a command run on a compose service
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, JsonSchema)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a command, a map with `run` and optionally `cwd`, `env` and `shell`, or a map with `service` and `run` or `exec`"
+)]
+pub enum TaskStep {
+    /// Runs the command in a new container of the service
+    Run {
+        /// The service to start a container of
+        service: String,
+        /// Runs the default command of the service when empty
+        #[serde(default = "String::new", skip_serializing_if = "String::is_empty")]
+        run: String,
+    },
+    /// Runs the command in the running container of the service
+    Exec {
+        /// The service whose container runs the command
+        service: String,
+        exec: String,
+    },
+    Command(HookCommand),
+}
+
+impl fmt::Display for TaskStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskStep::Command(command) => write!(f, "{}", command),
+            TaskStep::Run { service, run } if run.is_empty() => write!(f, "run {}", service),
+            TaskStep::Run { service, run } => write!(f, "run {}: {}", service, run),
+            TaskStep::Exec { service, exec } => write!(f, "exec {}: {}", service, exec),
+        }
+    }
+}
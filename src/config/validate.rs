@@ -0,0 +1,186 @@
+use crate::config::config::Config;
+use crate::config::path_buf_is_new;
+use crate::config::project::Project;
+use std::path::Path;
+use url::Url;
+
+/// Schemes of the urls git can clone
+const GIT_SCHEMES: [&str; 6] = ["http", "https", "ssh", "git", "git+ssh", "file"];
+
+/// Problems in a config that parses, but would make commands fail later.
+/// `dir` is the directory of the docat.yml, whose siblings are the projects.
+/// Projects may depend on projects of the `known` config, like the cached
+/// one, when they are not in this one.
+pub fn check(config: &Config, known: Option<&Config>, dir: &Path) -> Vec<String> {
+    config
+        .apps
+        .iter()
+        .flat_map(|(app_name, app)| {
+            let known_projects = known
+                .and_then(|known| known.apps.get(app_name))
+                .into_iter()
+                .flat_map(|app| &app.projects);
+            let projects_by_name = app
+                .projects
+                .iter()
+                .chain(known_projects)
+                .collect::<Vec<_>>();
+
+            let shared_dir = match path_buf_is_new(&app.config.shared_dir) {
+                true => dir.parent().unwrap_or(dir).to_path_buf(),
+                false => app.config.shared_dir.clone(),
+            };
+
+            let install_projects = app
+                .projects
+                .iter()
+                .filter(|(_, project)| project.is_install)
+                .map(|(dir_name, _)| dir_name.clone())
+                .collect::<Vec<_>>();
+            let install = (install_projects.len() > 1).then(|| {
+                format!(
+                    "{}: only one project can be the install project, found {}",
+                    app_name,
+                    install_projects.join(", ")
+                )
+            });
+
+            let projects = app.projects.iter().flat_map(|(dir_name, project)| {
+                let key = format!("{}.projects.{}", app_name, dir_name);
+                let project_dir = match path_buf_is_new(&project.dir) {
+                    true => shared_dir.join(dir_name),
+                    false => project.dir.clone(),
+                };
+
+                let depends_on = project
+                    .depends_on
+                    .iter()
+                    .filter(|dependency| {
+                        !projects_by_name.iter().any(|(dir_name, project)| {
+                            *dir_name == *dependency || project.name.as_ref() == Some(dependency)
+                        })
+                    })
+                    .map(|dependency| {
+                        format!(
+                            "{}.depends_on: {} is not a project of {}",
+                            key, dependency, app_name
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                check_project(&key, project, &project_dir)
+                    .into_iter()
+                    .chain(depends_on)
+            });
+
+            install.into_iter().chain(projects).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn check_project(key: &str, project: &Project, dir: &Path) -> Vec<String> {
+    let git = (!project.git.is_empty() && !is_git_url(&project.git))
+        .then(|| format!("{}.git: `{}` is not a git url", key, project.git));
+
+    // compose files can only be checked once the project is installed
+    let compose_files = project
+        .compose_files
+        .iter()
+        .filter(|_| dir.exists())
+        .filter(|file| !dir.join(file).exists())
+        .map(|file| {
+            format!(
+                "{}.compose_files: {} does not exist in {}",
+                key,
+                file,
+                dir.display()
+            )
+        });
+
+    git.into_iter().chain(compose_files).collect()
+}
+
+/// Whether git can clone from it: a url with a scheme, the scp-like
+/// `user@host:path` or a local path
+fn is_git_url(git: &str) -> bool {
+    if git.contains(char::is_whitespace) {
+        return false;
+    }
+    if git.contains("://") {
+        return Url::parse(git).is_ok_and(|url| {
+            GIT_SCHEMES.contains(&url.scheme())
+                && (url.has_host() || url.scheme() == "file")
+                && url.path().len() > 1
+        });
+    }
+    if ["/", "./", "../", "~/"]
+        .iter()
+        .any(|prefix| git.starts_with(prefix))
+    {
+        return true;
+    }
+
+    // git only reads it as scp-like when no slash comes before the colon
+    git.split_once(':').is_some_and(|(user_host, path)| {
+        let host = user_host.rsplit('@').next().unwrap_or_default();
+        !host.is_empty() && !user_host.contains('/') && !path.is_empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn depends_on_must_name_a_project() {
+        let shop = config(
+            "shop:
+  projects:
+    api:
+      depends_on: [dbb, database, cache]
+    postgres:
+      name: database
+",
+        );
+        let known = config("shop:\n  projects:\n    cache: {}\n");
+
+        assert_eq!(
+            check(&shop, Some(&known), Path::new("/nowhere")),
+            ["shop.projects.api.depends_on: dbb is not a project of shop"]
+        );
+        assert_eq!(check(&shop, None, Path::new("/nowhere")).len(), 2);
+    }
+
+    #[test]
+    fn git_urls() {
+        for url in [
+            "https://github.com/org/repo.git",
+            "ssh://git@host:2222/srv/repo.git",
+            "file:///srv/repo",
+            "git@github.com:org/repo.git",
+            "git@host:/srv/repo.git",
+            "host:repo",
+            "/srv/repo",
+            "../repo",
+            "~/repo",
+        ] {
+            assert!(is_git_url(url), "{}", url);
+        }
+
+        for url in [
+            "https://",
+            "ftp://host/repo",
+            "ssh://host:repo",
+            "repo",
+            "a/b:c",
+            "host:",
+            "git@host:a b",
+        ] {
+            assert!(!is_git_url(url), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::config::app::App;
+use crate::error::DocatError;
+use crate::{Project, ProjectDirName};
+use anyhow::{bail, Context, Result};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Add every project the given projects depend on, transitively
+pub fn resolve(
+    app: &App,
+    projects: &BTreeMap<ProjectDirName, Project>,
+) -> Result<BTreeMap<ProjectDirName, Project>> {
+    let mut resolved = projects.clone();
+    let mut pending = projects.values().cloned().collect::<Vec<_>>();
+
+    while let Some(project) = pending.pop() {
+        for dependency in &project.depends_on {
+            let dir_name = find(&app.projects, dependency)
+                .ok_or_else(|| DocatError::UnknownProject(dependency.clone()))
+                .with_context(|| format!("Could not resolve dependencies of {}", project.name()))?;
+
+            if let Entry::Vacant(entry) = resolved.entry(dir_name) {
+                let dependency = app.projects[entry.key()].clone();
+                pending.push(dependency.clone());
+                entry.insert(dependency);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Order projects so dependencies come before the projects that need them,
+/// dependencies outside of the given projects are ignored
+pub fn order(projects: &BTreeMap<ProjectDirName, Project>) -> Result<Vec<ProjectDirName>> {
+    let dependencies = dependencies(projects);
+    let mut ordered: Vec<ProjectDirName> = Vec::new();
+    let mut remaining = dependencies.clone();
+
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .filter(|(_, dependencies)| dependencies.iter().all(|dir| ordered.contains(dir)))
+            .map(|(dir_name, _)| dir_name.clone())
+            .collect::<Vec<_>>();
+
+        if ready.is_empty() {
+            bail!(DocatError::DependencyCycle(find_cycle(&remaining)));
+        }
+
+        ready.into_iter().for_each(|dir_name| {
+            remaining.remove(&dir_name);
+            ordered.push(dir_name);
+        });
+    }
+
+    Ok(ordered)
+}
+
+/// Map each project to the dependencies that are part of the given projects
+pub fn dependencies(
+    projects: &BTreeMap<ProjectDirName, Project>,
+) -> BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>> {
+    projects
+        .iter()
+        .map(|(dir_name, project)| {
+            (
+                dir_name.clone(),
+                project
+                    .depends_on
+                    .iter()
+                    .filter_map(|dependency| find(projects, dependency))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Map each project to the projects that depend on it, the reverse of `dependencies`
+pub fn dependents(
+    projects: &BTreeMap<ProjectDirName, Project>,
+) -> BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>> {
+    let mut dependents: BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>> = BTreeMap::new();
+
+    dependencies(projects)
+        .into_iter()
+        .for_each(|(dir_name, dependencies)| {
+            dependencies.into_iter().for_each(|dependency| {
+                dependents
+                    .entry(dependency)
+                    .or_default()
+                    .insert(dir_name.clone());
+            });
+        });
+
+    dependents
+}
+
+fn find(projects: &BTreeMap<ProjectDirName, Project>, name: &str) -> Option<ProjectDirName> {
+    projects
+        .iter()
+        .find(|(dir_name, project)| dir_name.as_str() == name || project.name() == name)
+        .map(|(dir_name, _)| dir_name.clone())
+}
+
+fn find_cycle(remaining: &BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>>) -> Vec<String> {
+    // every remaining project waits on another remaining project, so walking
+    // the first unresolved dependency must eventually revisit a project
+    let mut path: Vec<ProjectDirName> = Vec::new();
+    let mut current = remaining.keys().next().cloned();
+
+    while let Some(dir_name) = current {
+        if let Some(position) = path.iter().position(|visited| *visited == dir_name) {
+            let mut cycle = path.split_off(position);
+            cycle.push(dir_name);
+            return cycle;
+        }
+
+        current = remaining[&dir_name]
+            .iter()
+            .find(|dependency| remaining.contains_key(*dependency))
+            .cloned();
+        path.push(dir_name);
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app of `(dir name, name, depends on)` projects
+    fn app(projects: &[(&str, Option<&str>, &[&str])]) -> App {
+        let mut app = App::new();
+        for (dir_name, name, depends_on) in projects {
+            let project = app.add_project(dir_name);
+            project.dir_name = dir_name.to_string();
+            project.name = name.map(str::to_string);
+            project.depends_on = depends_on.iter().map(|dir| dir.to_string()).collect();
+        }
+        app
+    }
+
+    fn select(app: &App, dir_names: &[&str]) -> BTreeMap<ProjectDirName, Project> {
+        dir_names
+            .iter()
+            .map(|dir_name| (dir_name.to_string(), app.projects[*dir_name].clone()))
+            .collect()
+    }
+
+    fn shop() -> App {
+        app(&[
+            ("api", None, &["db"]),
+            ("cache", None, &[]),
+            ("db", None, &[]),
+            ("web", None, &["api", "cache"]),
+        ])
+    }
+
+    #[test]
+    fn resolve_adds_dependencies_transitively() {
+        let app = shop();
+        let resolved = resolve(&app, &select(&app, &["web"])).unwrap();
+
+        assert_eq!(
+            resolved.keys().collect::<Vec<_>>(),
+            ["api", "cache", "db", "web"]
+        );
+        assert_eq!(resolve(&app, &select(&app, &["db"])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn resolve_fails_on_an_unknown_dependency() {
+        let app = app(&[("api", None, &["dbb"])]);
+        let err = resolve(&app, &app.projects).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(DocatError::UnknownProject(name)) if name == "dbb"
+        ));
+    }
+
+    #[test]
+    fn order_puts_dependencies_first() {
+        let app = shop();
+
+        assert_eq!(order(&app.projects).unwrap(), ["cache", "db", "api", "web"]);
+        // dependencies that are not selected don't hold anything back
+        assert_eq!(
+            order(&select(&app, &["web", "api"])).unwrap(),
+            ["api", "web"]
+        );
+    }
+
+    #[test]
+    fn order_reports_the_cycle_path() {
+        let app = app(&[
+            ("a", None, &["b"]),
+            ("b", None, &["c"]),
+            ("c", None, &["a"]),
+            ("app", None, &["a"]),
+            ("db", None, &[]),
+        ]);
+        let err = order(&app.projects).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(DocatError::DependencyCycle(_))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Dependency cycle detected: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn dependents_reverse_dependencies_for_down() {
+        let app = shop();
+        let dependents = dependents(&app.projects);
+
+        assert_eq!(dependents.len(), 3);
+        assert_eq!(dependents["db"], BTreeSet::from(["api".to_string()]));
+        assert_eq!(dependents["api"], BTreeSet::from(["web".to_string()]));
+        assert_eq!(dependents["cache"], BTreeSet::from(["web".to_string()]));
+        assert!(!dependents.contains_key("web"));
+    }
+
+    #[test]
+    fn dependencies_can_be_named_by_project_name() {
+        let app = app(&[
+            ("api", None, &["database"]),
+            ("postgres", Some("database"), &[]),
+        ]);
+
+        assert_eq!(
+            dependencies(&app.projects)["api"],
+            BTreeSet::from(["postgres".to_string()])
+        );
+        assert_eq!(order(&app.projects).unwrap(), ["postgres", "api"]);
+        assert_eq!(
+            resolve(&app, &select(&app, &["api"]))
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["api", "postgres"]
+        );
+    }
+}
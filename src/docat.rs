@@ -1,20 +1,32 @@
+use crate::config::app::App;
 use crate::config::config::Config;
+use crate::config::project::Hook;
+use crate::config::task::TaskStep;
+use crate::config::validate;
+use crate::docker::{ContainerCmd, LogOptions};
+use crate::error::DocatError;
 use crate::file::{cached_config_file, cached_config_path, CONFIG_FILENAME};
-use crate::git::ConfigCmd;
-use crate::service::{Service, Status};
+use crate::git::{Checkout, ConfigCmd, RepoStatus, Update};
+use crate::lock::Lock;
+use crate::service::{ProjectStatus, Service};
+use crate::snapshot::Snapshot;
 use crate::{
-    cmd, config, cwd, docker, git, ComposeCmd, NetworkCmd, Parameters, Project, ProjectDirName,
-    VolumeCmd,
+    cmd, config, cwd, dependency, docker, file, git, lock, pool, ready, service, snapshot,
+    ComposeCmd, Format, NetworkCmd, Parameters, Project, ProjectDirName, VolumeCmd,
 };
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use console::{style, Color};
 use dialoguer::Confirm;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs;
-use std::io::BufRead;
+use std::io::{BufRead, ErrorKind};
 use std::path::Path;
+use std::process::Output;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn init(app_name: String) -> Result<()> {
-    let mut project_config_file = cwd();
+    let mut project_config_file = cwd()?;
     project_config_file.push(CONFIG_FILENAME);
     if Path::exists(&project_config_file) {
         println!("Config file found, skipping.");
@@ -22,12 +34,11 @@ pub fn init(app_name: String) -> Result<()> {
     }
 
     let cached_config =
-        &mut config::load_from(&cached_config_path()).or(Result::<Config>::Ok(Config::new()))?;
+        &mut config::load_from(&cached_config_path()?).or(Result::<Config>::Ok(Config::new()))?;
 
     // generate new project config
     let mut new_config = Config::new();
-    let config_filename = cwd();
-    let dir_name = config_filename.file_name().unwrap().to_str().unwrap();
+    let dir_name = &config::dir_name(&cwd()?);
     let new_app = new_config.add_app(&app_name);
 
     // if this is a new init
@@ -39,53 +50,45 @@ pub fn init(app_name: String) -> Result<()> {
 
         // add app config to cached config
         let cached_app = cached_config.add_app(&app_name);
-        cached_app.config.init(&cwd());
+        cached_app.config.init(&cwd()?);
 
-        set_git_config(project, dir_name)
+        set_git_config(project, dir_name)?
     } else {
         // add app to install config if it doesn't exist
-        let cached_app = cached_config.get(&app_name);
-        if !cached_app.projects.contains_key(dir_name) {
-            cached_app
-                .projects
-                .iter()
-                .find(|(_, project)| project.is_install)
-                .and_then(|(_, project)| {
-                    config::load_from(&project.dir)
-                        .ok()
-                        .and_then(|mut install_config| {
-                            install_config
-                                .apps
-                                .get(&app_name)
-                                .and_then(|project_app| {
-                                    project_app
-                                        .projects
-                                        .get(dir_name)
-                                        .cloned()
-                                        .or(Some(project_app.clone().add_project(dir_name).clone()))
-                                })
-                                .map(|mut new_project| {
-                                    let mut install_config_file = project.dir.clone();
-                                    install_config_file.push(CONFIG_FILENAME);
-                                    set_git_config(&mut new_project, dir_name);
-
-                                    install_config
-                                        .get(&app_name)
-                                        .projects
-                                        .insert(dir_name.parse().unwrap(), new_project);
-
-                                    // write project config
-                                    let config_yaml = serde_yaml::to_string(&install_config)
-                                        .expect("Could not create yaml");
-                                    fs::write(install_config_file, config_yaml)
-                                        .expect("Could not write config");
-                                })
-                        })
-                });
+        let cached_app = cached_config.get(&app_name)?;
+        let install_project = cached_app
+            .projects
+            .values()
+            .find(|project| project.is_install);
+
+        if let Some(install_project) =
+            install_project.filter(|_| !cached_app.projects.contains_key(dir_name))
+        {
+            if let Ok(mut install_config) = config::load_from(&install_project.dir) {
+                if let Some(project_app) = install_config.apps.get(&app_name) {
+                    let mut new_project = project_app
+                        .projects
+                        .get(dir_name)
+                        .cloned()
+                        .unwrap_or_else(Project::new);
+                    let mut install_config_file = install_project.dir.clone();
+                    install_config_file.push(CONFIG_FILENAME);
+                    set_git_config(&mut new_project, dir_name)?;
+
+                    install_config
+                        .get(&app_name)?
+                        .projects
+                        .insert(dir_name.clone(), new_project);
+
+                    // write project config
+                    let config_yaml = serde_yaml::to_string(&install_config)?;
+                    fs::write(install_config_file, config_yaml)?;
+                }
+            }
         }
     }
 
-    let cached_config = cached_config.merge(&new_config);
+    let cached_config = cached_config.merge(&new_config)?;
 
     let config_yaml = serde_yaml::to_string(&new_config)?;
     let cached_config_yaml = serde_yaml::to_string(&cached_config)?;
@@ -95,7 +98,7 @@ pub fn init(app_name: String) -> Result<()> {
         .interact()?
     {
         fs::write(project_config_file, config_yaml)?;
-        fs::write(cached_config_file(), cached_config_yaml)?;
+        fs::write(cached_config_file()?, cached_config_yaml)?;
         println!("Config file generated");
     } else {
         println!("Aborted");
@@ -104,74 +107,175 @@ pub fn init(app_name: String) -> Result<()> {
     Ok(())
 }
 
-pub fn install(parameters: &Parameters) {
-    parameters
+/// Report every problem in the docat.yml of a directory, a parse error
+/// stops at the first one
+pub fn validate_config(dir: &Path) -> Result<()> {
+    let config = config::load_from(dir)?;
+    let known = config::load_from(&cached_config_path()?).ok();
+    let problems = validate::check(&config, known.as_ref(), dir);
+    let path = dir.join(CONFIG_FILENAME);
+
+    if problems.is_empty() {
+        println!("{} is valid", path.display());
+        return Ok(());
+    }
+
+    problems
+        .iter()
+        .for_each(|problem| println!("  {}", problem));
+    bail!(DocatError::InvalidConfig(
+        path,
+        format!("{} problems found", problems.len())
+    ))
+}
+
+pub fn config_schema() -> Result<()> {
+    let mut schema = schemars::schema_for!(Config);
+    let metadata = schema.schema.metadata();
+    metadata.title = Some(CONFIG_FILENAME.to_string());
+    metadata.description = Some("Apps by name, with their projects and config".to_string());
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+
+    Ok(())
+}
+
+/// Clone projects that are missing. With a lock, every project is also
+/// moved to its locked commit.
+pub fn install(parameters: &Parameters, locked: bool) -> Result<()> {
+    let lock = match locked {
+        true => Some(lock::load(&parameters.app)?),
+        false => None,
+    };
+
+    let projects = parameters
         .projects
         .iter()
-        .filter(|(_, project)| !project.dir.exists() && !project.git.is_empty())
-        .for_each(|(dir_name, project)| {
-            git::clone(&project.git, &parameters.app.config.shared_dir);
+        .filter(|(_, project)| (!project.dir.exists() || lock.is_some()) && !project.git.is_empty())
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    let results = pool::run(
+        &projects,
+        dependency::dependencies(&projects),
+        parameters.jobs,
+        |project| {
+            let missing = !project.dir.exists();
+            if !missing {
+                return checkout_locked(project, lock.as_ref());
+            }
 
-            let mut project_dir = project.dir.clone();
-            project_dir.push(dir_name);
+            run_hook(&parameters.app, project, Hook::BeforeInstall)?;
+            git::clone(
+                &project.git,
+                &project.branch,
+                &parameters.app.config.shared_dir,
+            )?;
+            if !project.reference.is_empty() {
+                git::checkout(&project.reference, true, &project.dir)?;
+            }
+            checkout_locked(project, lock.as_ref())?;
 
             // combine the config from the new directory
-            let app = config::combine(&None).expect("Could not construct config");
-            let project = app.projects.get(dir_name).unwrap();
-
-            docker::network(NetworkCmd::Create(
-                parameters.app.config.shared_network.clone(),
-            ));
-            project.networks.iter().for_each(|network| {
-                docker::network(NetworkCmd::Create(network.clone()));
-            });
+            let app = config::combine(&None)?;
+            let project = app
+                .projects
+                .get(&project.dir_name)
+                .ok_or_else(|| DocatError::UnknownProject(project.dir_name.clone()))?;
 
-            project.volumes.iter().for_each(|volume| {
-                docker::volume(VolumeCmd::Create(volume.clone()));
-            });
+            create_networks_and_volumes(parameters, project)?;
 
-            cmd::run_from_list(
-                &project.on_install,
-                &project.dir,
-                "Could not run install command",
-            );
-        });
+            run_hook(&app, project, Hook::OnInstall)?;
+            run_hook(&app, project, Hook::AfterInstall)
+        },
+    );
+
+    pool::summarize(&projects, results).map(|_| ())
+}
+
+/// Move a project to the commit in the lock, if there is one
+fn checkout_locked(project: &Project, lock: Option<&Lock>) -> Result<()> {
+    let commit = match lock.and_then(|lock| lock.projects.get(&project.dir_name)) {
+        Some(locked) => &locked.commit,
+        None => return Ok(()),
+    };
+
+    // nothing was cloned in a dry run
+    if cmd::is_dry_run() && !project.dir.exists() {
+        return git::checkout(commit, true, &project.dir).map(|_| ());
+    }
+
+    if git::rev_parse("HEAD", &project.dir)? == *commit {
+        return Ok(());
+    }
+
+    if git::is_dirty(&project.dir)? {
+        bail!("Uncommitted changes, not checking out {}", commit);
+    }
+
+    if git::rev_parse(&format!("{}^{{commit}}", commit), &project.dir).is_err() {
+        git::fetch(&project.dir)?;
+    }
+
+    git::checkout(commit, true, &project.dir)?;
+
+    Ok(())
+}
+
+pub fn lock(app: &App) -> Result<()> {
+    let lock = lock::create(app)?;
+    if cmd::is_dry_run() {
+        println!("Would write {}", lock::path(app).display());
+        return Ok(());
+    }
+
+    let path = lock::save(app, &lock)?;
+    println!(
+        "Locked {} projects in {}",
+        lock.projects.len(),
+        path.display()
+    );
+
+    Ok(())
 }
 
-pub fn run_install(parameters: &Parameters) {
+pub fn run_install(parameters: &Parameters) -> Result<()> {
     if parameters.projects.is_empty() {
-        panic!("Cannot run install on all projects")
+        bail!("Cannot run install on all projects")
     }
 
-    parameters.projects.iter().for_each(|(_, project)| {
-        docker::network(NetworkCmd::Create(
-            parameters.app.config.shared_network.clone(),
-        ));
-        project.networks.iter().for_each(|network| {
-            docker::network(NetworkCmd::Create(network.clone()));
-        });
+    let results = pool::run(
+        &parameters.projects,
+        dependency::dependencies(&parameters.projects),
+        parameters.jobs,
+        |project| {
+            create_networks_and_volumes(parameters, project)?;
 
-        project.volumes.iter().for_each(|volume| {
-            docker::volume(VolumeCmd::Create(volume.clone()));
-        });
+            run_hook(&parameters.app, project, Hook::BeforeInstall)?;
+            run_hook(&parameters.app, project, Hook::OnInstall)?;
+            run_hook(&parameters.app, project, Hook::AfterInstall)
+        },
+    );
 
-        cmd::run_from_list(
-            &project.on_install,
-            &project.dir,
-            "Could not run install command",
-        );
-    });
+    pool::summarize(&parameters.projects, results).map(|_| ())
 }
 
-pub fn up(parameters: &Parameters) {
-    install(parameters);
+pub fn up(parameters: &Parameters) -> Result<()> {
+    let parameters = &with_dependencies(parameters)?;
+    dependency::order(&parameters.projects)?;
+
+    install(parameters, false)?;
 
-    let down_projects = statuses(parameters)
+    let down_projects = statuses(parameters)?
         .iter()
-        .filter(|(_, services)| {
-            services
-                .iter()
-                .any(|service| service.status == Status::Down)
+        .filter(|(dir_name, services)| {
+            let wanted = parameters.services(dir_name);
+
+            // a project that was not cloned, e.g. in a dry run, is down
+            !parameters.projects[*dir_name].dir.exists()
+                || services
+                    .iter()
+                    .filter(|service| wanted.is_empty() || wanted.contains(&service.name))
+                    .any(|service| service.status.is_down())
         })
         .collect::<BTreeMap<_, _>>()
         .keys()
@@ -179,146 +283,1174 @@ pub fn up(parameters: &Parameters) {
         .cloned()
         .collect::<HashSet<String>>();
 
-    docker::network(NetworkCmd::Create(
-        parameters.app.config.shared_network.clone(),
-    ));
-
-    parameters
-        .app
+    let projects = parameters
         .projects
         .iter()
-        .filter(|(dir_name, _)| down_projects.contains(dir_name.clone()))
-        .for_each(|(_, project)| {
-            project.networks.iter().for_each(|network| {
-                docker::network(NetworkCmd::Create(network.clone()));
-            });
+        .filter(|(dir_name, _)| down_projects.contains(*dir_name))
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect::<BTreeMap<_, _>>();
 
-            project.volumes.iter().for_each(|volume| {
-                docker::volume(VolumeCmd::Create(volume.clone()));
-            });
+    let results = pool::run(
+        &projects,
+        dependency::dependencies(&projects),
+        parameters.jobs,
+        |project| {
+            create_networks_and_volumes(parameters, project)?;
 
-            cmd::run_from_list(&project.on_up, &project.dir, "Could not run build command");
+            run_hook(&parameters.app, project, Hook::OnUp)?;
 
             docker::compose(
-                ComposeCmd::Up(Vec::new(), project.compose_files.clone()),
+                ComposeCmd::Up(
+                    parameters.services(&project.dir_name),
+                    project.compose_files.clone(),
+                ),
                 &project.dir,
+            )
+            .context("compose up")?;
+
+            ready::wait(project, &project_env(&parameters.app, project)).context("ready")?;
+
+            run_hook(&parameters.app, project, Hook::AfterUp)
+        },
+    );
+
+    pool::summarize(&projects, results).map(|_| ())
+}
+
+pub fn down(parameters: &Parameters) -> Result<()> {
+    // make sure there are no cycles before bringing anything down
+    dependency::order(&parameters.projects)?;
+
+    // bring dependencies down last
+    let results = pool::run(
+        &parameters.projects,
+        dependency::dependents(&parameters.projects),
+        parameters.jobs,
+        |project| {
+            run_hook(&parameters.app, project, Hook::BeforeDown)?;
+
+            let services = parameters.services(&project.dir_name);
+            match services.is_empty() {
+                true => {
+                    docker::compose(
+                        ComposeCmd::Down(project.compose_files.clone()),
+                        &project.dir,
+                    )
+                    .context("compose down")?;
+                }
+                false => {
+                    let files = &project.compose_files;
+                    docker::compose(
+                        ComposeCmd::Stop(services.clone(), files.clone()),
+                        &project.dir,
+                    )
+                    .context("compose stop")?;
+                    docker::compose(ComposeCmd::Rm(services, files.clone()), &project.dir)
+                        .context("compose rm")?;
+                }
+            }
+
+            run_hook(&parameters.app, project, Hook::AfterDown)
+        },
+    );
+
+    pool::summarize(&parameters.projects, results).map(|_| ())
+}
+
+/// Bring projects down and delete or trash their directories, but only if
+/// that cannot lose any work that is not pushed
+pub fn uninstall(parameters: &Parameters, trash: bool, force: bool) -> Result<()> {
+    if let Some(project) = parameters
+        .projects
+        .values()
+        .find(|project| project.is_install)
+    {
+        bail!(
+            "{} is the install project and holds the config",
+            project.name()
+        );
+    }
+
+    let projects = parameters
+        .projects
+        .iter()
+        .filter(|(_, project)| project.dir.exists())
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    if !force {
+        let results =
+            pool::run(
+                &projects,
+                BTreeMap::new(),
+                parameters.jobs,
+                |project| match unpushed_work(&project.dir)? {
+                    Some(work) => bail!("{}, pass --force to remove it anyway", work),
+                    None => Ok(()),
+                },
             );
+        pool::summarize(&projects, results)?;
+    }
+
+    down(&Parameters {
+        projects: projects.clone(),
+        services: BTreeMap::new(),
+        ..parameters.clone()
+    })?;
+
+    let results = pool::run(&projects, BTreeMap::new(), parameters.jobs, |project| {
+        remove_dir(&project.dir, trash)
+    });
+    let removed = results
+        .iter()
+        .filter(|(_, result)| result.is_ok())
+        .map(|(dir_name, _)| dir_name.clone())
+        .collect::<Vec<_>>();
 
-            cmd::run_from_list(
-                &project.after_up,
+    if !cmd::is_dry_run() {
+        config::forget(&parameters.app.name, &removed)?;
+    }
+
+    pool::summarize(&projects, results).map(|_| ())
+}
+
+/// Why removing the checkout would lose work, if it would
+fn unpushed_work(dir: &Path) -> Result<Option<String>> {
+    if !git::is_repository(dir)? {
+        return Ok(Some("Not a git repository".to_string()));
+    }
+
+    let changes = git::changes(dir)?;
+    let unpushed = git::unpushed(dir)?;
+    let stashes = git::stashes(dir)?;
+
+    Ok(match (changes, unpushed, stashes) {
+        (0, 0, 0) => None,
+        (0, 0, stashes) => Some(format!("{} stashed changes", stashes)),
+        (0, unpushed, _) => Some(format!("{} commits not pushed", unpushed)),
+        (changes, _, _) => Some(format!("{} uncommitted changes", changes)),
+    })
+}
+
+fn remove_dir(dir: &Path, trash: bool) -> Result<()> {
+    let trash_dir = match trash {
+        true => {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            Some(file::trash_path()?.join(format!("{}-{}", config::dir_name(dir), seconds)))
+        }
+        false => None,
+    };
+
+    if cmd::is_dry_run() {
+        match &trash_dir {
+            Some(trash_dir) => println!("Would move {} to {}", dir.display(), trash_dir.display()),
+            None => println!("Would remove {}", dir.display()),
+        }
+        return Ok(());
+    }
+
+    match trash_dir {
+        Some(trash_dir) => {
+            fs::create_dir_all(file::trash_path()?)?;
+            match fs::rename(dir, &trash_dir) {
+                // the trash is on another filesystem, so copy instead
+                Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+                    file::copy_dir(dir, &trash_dir)
+                        .and_then(|_| Ok(fs::remove_dir_all(dir)?))
+                        .with_context(|| {
+                            format!(
+                                "Could not copy {} to the trash on another filesystem",
+                                dir.display()
+                            )
+                        })
+                }
+                result => {
+                    result.with_context(|| format!("Could not move {} to the trash", dir.display()))
+                }
+            }
+        }
+        None => {
+            fs::remove_dir_all(dir).with_context(|| format!("Could not remove {}", dir.display()))
+        }
+    }
+}
+
+/// Remove everything `up` and `install` created for the app, after showing
+/// exactly what will be deleted
+pub fn destroy(parameters: &Parameters, yes: bool) -> Result<()> {
+    dependency::order(&parameters.projects)?;
+
+    let projects = parameters
+        .projects
+        .iter()
+        .filter(|(_, project)| project.dir.exists())
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let volumes = parameters
+        .projects
+        .values()
+        .flat_map(|project| project.volumes.clone())
+        .collect::<BTreeSet<_>>();
+    let networks = parameters
+        .projects
+        .values()
+        .flat_map(|project| project.networks.clone())
+        .chain([parameters.app.config.shared_network.clone()])
+        .filter(|network| !network.is_empty())
+        .collect::<BTreeSet<_>>();
+
+    let compose_volumes = projects
+        .values()
+        .map(|project| Ok((project.name(), docker::compose_volumes(&project.dir_name)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("This will remove");
+    projects.values().for_each(|project| {
+        println!("  containers of {}", project.name());
+    });
+    compose_volumes.iter().for_each(|(name, project_volumes)| {
+        project_volumes
+            .iter()
+            .filter(|volume| !volumes.contains(*volume))
+            .for_each(|volume| println!("  volume {} of {}", volume, name));
+    });
+    volumes
+        .iter()
+        .for_each(|volume| println!("  volume {}", volume));
+    networks
+        .iter()
+        .for_each(|network| println!("  network {}", network));
+
+    if !yes && !cmd::is_dry_run() && !console::user_attended_stderr() {
+        bail!("Not destroying without confirmation, pass --yes to skip it");
+    }
+
+    if !yes
+        && !cmd::is_dry_run()
+        && !Confirm::new()
+            .with_prompt(format!("Destroy {}?", parameters.app.name))
+            .default(false)
+            .interact()?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    // bring dependencies down last
+    let results = pool::run(
+        &projects,
+        dependency::dependents(&projects),
+        parameters.jobs,
+        |project| {
+            docker::compose(
+                ComposeCmd::Destroy(project.compose_files.clone()),
                 &project.dir,
-                "Could not run after up hooks",
-            );
+            )
+            .context("compose down")?;
+
+            Ok(())
+        },
+    );
+    pool::summarize(&projects, results)?;
+
+    volumes
+        .into_iter()
+        .try_for_each(|volume| docker::volume(VolumeCmd::Remove(volume)).map(|_| ()))?;
+    networks
+        .into_iter()
+        .try_for_each(|network| docker::network(NetworkCmd::Remove(network)).map(|_| ()))
+}
+
+/// Archive the volumes of the projects, with the containers that use them
+/// stopped so the files are consistent
+pub fn snapshot_save(parameters: &Parameters, name: &str) -> Result<()> {
+    let dir = snapshot::path(&parameters.app.name, name)?;
+    if dir.exists() {
+        bail!(
+            "Snapshot {} already exists, remove it first with docat snapshot rm {}",
+            name,
+            name
+        );
+    }
+
+    let mut owned = Vec::new();
+    for project in parameters.projects.values() {
+        owned.push((project.dir_name.clone(), project_volumes(project)?));
+    }
+    let snapshot = Snapshot {
+        projects: single_owners(owned),
+    };
+    if snapshot.projects.is_empty() {
+        bail!("No volumes to snapshot");
+    }
+
+    match cmd::is_dry_run() {
+        true => println!("Would save snapshot to {}", dir.display()),
+        false => fs::create_dir_all(&dir)?,
+    }
+
+    let projects = owning_projects(&parameters.projects, &snapshot);
+    let result = with_volumes_unused(&snapshot, || {
+        let results = pool::run(&projects, BTreeMap::new(), parameters.jobs, |project| {
+            snapshot.projects[&project.dir_name]
+                .iter()
+                .try_for_each(|volume| {
+                    let archive = snapshot::archive(&dir, volume);
+                    docker::volume(VolumeCmd::Archive(volume.clone(), archive))
+                        .with_context(|| format!("Could not archive volume {}", volume))
+                        .map(|_| ())
+                })
+        });
+        pool::summarize(&projects, results).map(|_| ())
+    });
+    if result.is_err() && dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    result?;
+
+    if !cmd::is_dry_run() {
+        snapshot::save(&dir, &snapshot)?;
+    }
+    println!(
+        "Saved {} volumes to snapshot {}",
+        snapshot.volume_count(),
+        name
+    );
+
+    Ok(())
+}
+
+/// Replace the contents of every volume in the snapshot with the archived
+/// ones, with the containers that use them stopped
+pub fn snapshot_restore(parameters: &Parameters, name: &str) -> Result<()> {
+    let loaded = snapshot::load(&parameters.app.name, name)?;
+    let dir = snapshot::path(&parameters.app.name, name)?;
+
+    if let Some(dir_name) = loaded
+        .projects
+        .keys()
+        .find(|dir_name| !parameters.app.projects.contains_key(*dir_name))
+    {
+        bail!(DocatError::UnknownProject(dir_name.clone()));
+    }
+
+    let snapshot = Snapshot {
+        projects: single_owners(loaded.projects.into_iter().collect()),
+    };
+    if let Some(volume) = snapshot
+        .projects
+        .values()
+        .flatten()
+        .find(|volume| !snapshot::archive(&dir, volume).is_file())
+    {
+        bail!("Snapshot {} has no archive of volume {}", name, volume);
+    }
+
+    let projects = owning_projects(&parameters.app.projects, &snapshot);
+    with_volumes_unused(&snapshot, || {
+        let results = pool::run(&projects, BTreeMap::new(), parameters.jobs, |project| {
+            snapshot.projects[&project.dir_name]
+                .iter()
+                .try_for_each(|volume| {
+                    let archive = snapshot::archive(&dir, volume);
+                    docker::volume(VolumeCmd::Restore(volume.clone(), archive))
+                        .with_context(|| format!("Could not restore volume {}", volume))
+                        .map(|_| ())
+                })
         });
+        pool::summarize(&projects, results).map(|_| ())
+    })?;
+    println!(
+        "Restored {} volumes from snapshot {}",
+        snapshot.volume_count(),
+        name
+    );
+
+    Ok(())
 }
 
-pub fn down(parameters: &Parameters) {
-    parameters.projects.iter().for_each(|(_, project)| {
-        docker::compose(ComposeCmd::Down, &project.dir);
+pub fn snapshot_list(app: &App) -> Result<()> {
+    let snapshots = snapshot::list(&app.name)?;
+    if snapshots.is_empty() {
+        println!("No snapshots of {}", app.name);
+    }
+
+    let width = snapshots.keys().map(String::len).max().unwrap_or_default();
+    snapshots.iter().for_each(|(name, snapshot)| {
+        println!(
+            "{:width$}  {}",
+            name,
+            snapshot
+                .projects
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+            width = width
+        )
     });
+
+    Ok(())
 }
 
-pub fn restart(parameters: &Parameters) {
-    down(parameters);
-    up(parameters);
+pub fn snapshot_rm(app: &App, name: &str) -> Result<()> {
+    snapshot::load(&app.name, name)?;
+    let dir = snapshot::path(&app.name, name)?;
+
+    match cmd::is_dry_run() {
+        true => println!("Would remove {}", dir.display()),
+        false => fs::remove_dir_all(&dir)
+            .with_context(|| format!("Could not remove {}", dir.display()))?,
+    }
+
+    Ok(())
 }
 
-pub fn status(parameters: &Parameters) {
-    statuses(parameters)
+/// The named volumes of the project and the volumes compose created for it
+fn project_volumes(project: &Project) -> Result<Vec<String>> {
+    Ok(project
+        .volumes
         .iter()
-        .for_each(|(dir_name, services)| {
+        .cloned()
+        .chain(docker::compose_volumes(&project.dir_name)?)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+/// Give every volume to the first project that has it, so projects sharing
+/// a volume don't archive or restore it at the same time
+fn single_owners(
+    volumes: Vec<(ProjectDirName, Vec<String>)>,
+) -> BTreeMap<ProjectDirName, Vec<String>> {
+    let mut seen = BTreeSet::new();
+
+    volumes
+        .into_iter()
+        .map(|(dir_name, volumes)| {
+            let volumes = volumes
+                .into_iter()
+                .filter(|volume| seen.insert(volume.clone()))
+                .collect::<Vec<_>>();
+            (dir_name, volumes)
+        })
+        .filter(|(_, volumes)| !volumes.is_empty())
+        .collect()
+}
+
+fn owning_projects(
+    projects: &BTreeMap<ProjectDirName, Project>,
+    snapshot: &Snapshot,
+) -> BTreeMap<ProjectDirName, Project> {
+    projects
+        .iter()
+        .filter(|(dir_name, _)| snapshot.projects.contains_key(*dir_name))
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect()
+}
+
+/// Stop every running container that mounts a volume of the snapshot, of
+/// any project, while `action` runs and start them again afterwards,
+/// whether it succeeded or not
+fn with_volumes_unused<F: FnOnce() -> Result<()>>(snapshot: &Snapshot, action: F) -> Result<()> {
+    let volumes = snapshot
+        .projects
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    let running = docker::volume_users(&volumes)?;
+
+    if !running.is_empty() {
+        docker::container(ContainerCmd::Stop(running.clone()))
+            .context("Could not stop containers")?;
+    }
+
+    let result = action();
+
+    if !running.is_empty() {
+        docker::container(ContainerCmd::Start(running)).context("Could not start containers")?;
+    }
+
+    result
+}
+
+/// Projects are brought down and up again, unless only some of their
+/// services should restart, which are stopped and started instead
+pub fn restart(parameters: &Parameters) -> Result<()> {
+    let whole = Parameters {
+        projects: parameters
+            .projects
+            .iter()
+            .filter(|(dir_name, _)| parameters.services(dir_name).is_empty())
+            .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+            .collect(),
+        ..parameters.clone()
+    };
+
+    if !whole.projects.is_empty() {
+        down(&whole)?;
+    }
+
+    let results = pool::run(
+        &parameters.projects,
+        dependency::dependencies(&parameters.projects),
+        parameters.jobs,
+        |project| {
+            let services = parameters.services(&project.dir_name);
+            if services.is_empty() {
+                return run_hook(&parameters.app, project, Hook::OnRestart);
+            }
+
+            let files = &project.compose_files;
+            docker::compose(
+                ComposeCmd::Stop(services.clone(), files.clone()),
+                &project.dir,
+            )
+            .context("compose stop")?;
+            run_hook(&parameters.app, project, Hook::OnRestart)?;
+            docker::compose(ComposeCmd::Start(services, files.clone()), &project.dir)
+                .context("compose start")?;
+
+            Ok(())
+        },
+    );
+    pool::summarize(&parameters.projects, results)?;
+
+    match whole.projects.is_empty() {
+        true => Ok(()),
+        false => up(&whole),
+    }
+}
+
+/// Fast-forward every project checkout to its upstream branch, or move it
+/// to its pinned ref, leaving repos with uncommitted changes or diverged
+/// branches alone
+pub fn update(parameters: &Parameters, run_install: bool) -> Result<()> {
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| {
+            if !project.dir.exists() {
+                return Ok(Update::NotInstalled);
+            }
+
+            if !git::is_repository(&project.dir)? {
+                return Ok(Update::NotARepository);
+            }
+
+            if git::is_dirty(&project.dir)? {
+                bail!("Uncommitted changes, not updating");
+            }
+
+            let update = update_checkout(project)?;
+            if run_install && update.moved() {
+                run_hook(&parameters.app, project, Hook::OnInstall)?;
+            }
+
+            Ok(update)
+        },
+    );
+
+    let width = parameters
+        .projects
+        .values()
+        .map(|project| project.name().len())
+        .max()
+        .unwrap_or_default();
+    println!();
+    results.iter().for_each(|(dir_name, result)| {
+        let update = match result {
+            Ok(update) => update.to_string(),
+            Err(_) => "failed".to_string(),
+        };
+        println!(
+            "  {:width$}  {}",
+            parameters.projects[dir_name].name(),
+            update
+        );
+    });
+
+    pool::summarize(&parameters.projects, results).map(|_| ())
+}
+
+fn update_checkout(project: &Project) -> Result<Update> {
+    let dir = &project.dir;
+    git::fetch(dir)?;
+    let head = git::rev_parse("HEAD", dir)?;
+
+    if !project.reference.is_empty() {
+        let target = git::rev_parse(&format!("{}^{{commit}}", project.reference), dir)?;
+        if head == target {
+            return Ok(Update::UpToDate);
+        }
+
+        git::checkout(&project.reference, true, dir)?;
+        return Ok(Update::CheckedOut {
+            from: head,
+            to: project.reference.clone(),
+        });
+    }
+
+    let switched = !project.branch.is_empty()
+        && git::current_branch(dir)?.as_deref() != Some(project.branch.as_str());
+    if switched {
+        git::checkout(&project.branch, false, dir)?;
+    }
+
+    let branch_head = git::rev_parse("HEAD", dir)?;
+    let upstream = git::rev_parse("@{upstream}", dir)?;
+
+    if branch_head != upstream && !git::is_ancestor(&upstream, &branch_head, dir)? {
+        if !git::is_ancestor(&branch_head, &upstream, dir)? {
+            bail!("Branch has diverged from upstream, not updating");
+        }
+
+        git::fast_forward(dir)?;
+    } else if !switched {
+        return Ok(match branch_head == upstream {
+            true => Update::UpToDate,
+            false => Update::Ahead,
+        });
+    }
+
+    Ok(match switched {
+        true => Update::CheckedOut {
+            from: head,
+            to: project.branch.clone(),
+        },
+        false => Update::Updated {
+            from: head,
+            to: upstream,
+        },
+    })
+}
+
+/// The branch and commit of a checkout, and whether it has left the
+/// branch or ref it is pinned to
+fn checkout(project: &Project) -> Result<Option<Checkout>> {
+    if !project.dir.exists() || !git::is_repository(&project.dir)? {
+        return Ok(None);
+    }
+
+    let branch = git::current_branch(&project.dir)?;
+    let head = git::rev_parse("HEAD", &project.dir)?;
+    let drifted = match (project.reference.is_empty(), project.branch.is_empty()) {
+        (false, _) => {
+            let pinned = format!("{}^{{commit}}", project.reference);
+            git::rev_parse(&pinned, &project.dir).map_or(true, |commit| commit != head)
+        }
+        (true, false) => branch.as_deref() != Some(project.branch.as_str()),
+        (true, true) => false,
+    };
+
+    Ok(Some(Checkout {
+        branch,
+        head,
+        pinned: project.pinned().map(str::to_string),
+        drifted,
+    }))
+}
+
+/// A project whose status could not be read is reported with the error
+/// instead of failing the others
+pub fn status(parameters: &Parameters, format: Format, show_git: bool) -> Result<()> {
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| {
+            let checkout = match show_git {
+                true => checkout(project)?,
+                false => None,
+            };
+
+            Ok((checkout, services(project)?))
+        },
+    );
+
+    let mut errors = Vec::new();
+    let statuses = results
+        .into_iter()
+        .map(|(dir_name, result)| {
+            let project = &parameters.projects[&dir_name];
+            let (checkout, services, error) = match result {
+                Ok((checkout, services)) => (checkout, services, None),
+                Err(err) => {
+                    let error = format!("{:#}", err);
+                    errors.push(err);
+                    (None, Vec::new(), Some(error))
+                }
+            };
+            let status = ProjectStatus {
+                name: project.name(),
+                dir: project.dir.clone(),
+                git: project.git.clone(),
+                checkout,
+                services,
+                error,
+            };
+
+            (dir_name, status)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&statuses)?),
+        Format::Yaml => print!("{}", serde_yaml::to_string(&statuses)?),
+        Format::Table => statuses.values().for_each(|status| {
             println!();
-            println!(
-                "{}",
-                parameters
-                    .app
-                    .projects
-                    .get(dir_name)
-                    .map(|project| project.name())
-                    .unwrap_or(dir_name.clone())
-            );
-            services.iter().for_each(|service| {
-                println!("  {}  {}", service.status, service.name);
+            match &status.checkout {
+                Some(checkout) => println!("{}  {}", status.name, checkout),
+                None => println!("{}", status.name),
+            }
+
+            if let Some(error) = &status.error {
+                println!("  {}", error);
+            }
+
+            let state_width = column_width(&status.services, Service::state);
+            let name_width = column_width(&status.services, |service| service.name.clone());
+            status.services.iter().for_each(|service| {
+                let line = format!(
+                    "  {:state_width$}  {:name_width$}  {}",
+                    service.state(),
+                    service.name,
+                    service.ports()
+                );
+                println!("{}", line.trim_end());
             });
-        });
+        }),
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => bail!(pool::projects_failed(errors.iter(), statuses.len())),
+    }
+}
+
+fn column_width<F: Fn(&Service) -> String>(services: &[Service], column: F) -> usize {
+    services
+        .iter()
+        .map(|service| column(service).len())
+        .max()
+        .unwrap_or_default()
 }
 
-pub fn run(service: &String, command: &Vec<String>, project: &Project) {
+pub fn run(service: &str, command: &[String], project: &Project) -> Result<()> {
     docker::compose(
         ComposeCmd::Run(
-            service.clone(),
+            service.to_string(),
             project.compose_files.clone(),
-            command.clone(),
+            command.to_vec(),
         ),
         &project.dir,
-    );
+    )
+    .and_then(exited)
 }
 
-pub fn exec(service: &String, command: &Vec<String>, project: &Project) {
+pub fn exec(service: &str, command: &[String], project: &Project) -> Result<()> {
     docker::compose(
         ComposeCmd::Exec(
-            service.clone(),
+            service.to_string(),
             project.compose_files.clone(),
-            command.clone(),
+            command.to_vec(),
         ),
         &project.dir,
+    )
+    .and_then(exited)
+}
+
+/// Pass on the exit status of a command that ran in a container
+fn exited(output: Output) -> Result<()> {
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(code) => bail!(DocatError::Exited(code)),
+        None => bail!("The command was terminated by a signal"),
+    }
+}
+
+/// Run a command in every project directory and report how each one exited
+pub fn foreach(parameters: &Parameters, command: &[String]) -> Result<()> {
+    let width = parameters
+        .projects
+        .values()
+        .map(|project| project.name().len())
+        .max()
+        .unwrap_or_default();
+
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| {
+            if !project.dir.exists() {
+                bail!("Not installed");
+            }
+
+            let mut cmd_wrapper = cmd::new(&command[0], &project.dir);
+            cmd_wrapper.command.args(&command[1..]);
+            cmd::set_prefix(Some(format!("{:width$} | ", project.name())));
+
+            cmd::run_checked(cmd_wrapper)
+        },
     );
+
+    println!();
+    results.iter().for_each(|(dir_name, result)| {
+        let status = match result {
+            Ok(_) => "ok".to_string(),
+            Err(err) => match err.downcast_ref::<DocatError>() {
+                Some(DocatError::CommandFailed {
+                    code: Some(code), ..
+                }) => format!("exited with {}", code),
+                _ => "failed".to_string(),
+            },
+        };
+        println!(
+            "  {:width$}  {}",
+            parameters.projects[dir_name].name(),
+            status
+        );
+    });
+
+    pool::summarize(&parameters.projects, results).map(|_| ())
 }
 
-fn statuses(parameters: &Parameters) -> BTreeMap<ProjectDirName, Vec<Service>> {
-    parameters
+/// Run the steps of a task on every project that defines it. Projects that
+/// were selected explicitly must define it.
+pub fn task(parameters: &Parameters, name: &str, selected: bool) -> Result<()> {
+    if let Some(project) = parameters
+        .projects
+        .values()
+        .find(|project| selected && !project.tasks.contains_key(name))
+    {
+        bail!("{} does not define task {}", project.name(), name);
+    }
+
+    let projects = parameters
         .projects
         .iter()
-        .map(|(dir_name, project)| {
-            let mut statuses: BTreeMap<String, Service> = String::from_utf8(
-                docker::compose(
-                    ComposeCmd::List(project.compose_files.clone()),
-                    &project.dir,
-                )
-                .stdout,
-            )
-            .ok()
-            .and_then(|json| serde_json::from_str(&json).ok())
-            .map(|services: Vec<Service>| {
-                services
-                    .into_iter()
-                    .map(|service| (service.name.clone(), service))
-                    .collect()
+        .filter(|(_, project)| project.tasks.contains_key(name))
+        .map(|(dir_name, project)| (dir_name.clone(), project.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    if projects.is_empty() {
+        bail!("No project defines task {}", name);
+    }
+
+    let results = pool::run(
+        &projects,
+        dependency::dependencies(&projects),
+        parameters.jobs,
+        |project| {
+            project.tasks[name].iter().try_for_each(|step| {
+                run_step(&parameters.app, project, step).with_context(|| step.to_string())
             })
-            .unwrap_or_default();
+        },
+    );
 
-            let services: Vec<_> = docker::compose(
-                ComposeCmd::Config(project.compose_files.clone()),
+    pool::summarize(&projects, results).map(|_| ())
+}
+
+fn run_step(app: &App, project: &Project, step: &TaskStep) -> Result<()> {
+    let env = project_env(app, project);
+
+    match step {
+        TaskStep::Command(command) => {
+            if let Some(cmd_wrapper) =
+                command.build(project.shell.as_deref(), &project.dir, &env)?
+            {
+                cmd::run_checked(cmd_wrapper)?;
+            }
+        }
+        TaskStep::Run { service, run } => {
+            exited(docker::compose(
+                ComposeCmd::Run(
+                    service.clone(),
+                    project.compose_files.clone(),
+                    cmd::split(run, &env)?,
+                ),
                 &project.dir,
-            )
-            .stdout
-            .as_slice()
-            .lines()
-            .flat_map(|line| line)
-            .map(|service_name| {
-                statuses.remove(&*service_name).unwrap_or(Service {
-                    name: service_name,
-                    status: Status::Down,
-                })
+            )?)?;
+        }
+        TaskStep::Exec { service, exec } => {
+            exited(docker::compose(
+                ComposeCmd::Exec(
+                    service.clone(),
+                    project.compose_files.clone(),
+                    cmd::split(exec, &env)?,
+                ),
+                &project.dir,
+            )?)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn list_tasks(app: &App) {
+    app.projects
+        .values()
+        .filter(|project| !project.tasks.is_empty())
+        .for_each(|project| {
+            println!();
+            println!("{}", project.name());
+            project.tasks.iter().for_each(|(name, steps)| {
+                println!("  {}", name);
+                steps.iter().for_each(|step| println!("    {}", step));
+            });
+        });
+}
+
+pub fn git_status(parameters: &Parameters) -> Result<()> {
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| Ok((project.name(), RepoStatus::of(&project.dir)?.columns())),
+    );
+    let rows = pool::summarize(&parameters.projects, results)?
+        .into_iter()
+        .map(|(name, columns)| std::iter::once(name).chain(columns).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let widths = rows.iter().fold(Vec::new(), |mut widths, row| {
+        widths.resize(widths.len().max(row.len()), 0);
+        row.iter()
+            .zip(widths.iter_mut())
+            .for_each(|(column, width)| *width = column.len().max(*width));
+        widths
+    });
+
+    println!();
+    rows.iter().for_each(|row| {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{:width$}", column))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("  {}", line.trim_end());
+    });
+
+    Ok(())
+}
+
+/// Stream the logs of every service at the same time, each line prefixed
+/// with the project and service it came from
+pub fn logs(parameters: &Parameters, services: &[String], options: &LogOptions) -> Result<()> {
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| {
+            if !project.dir.exists() {
+                return Ok((project.dir_name.clone(), Vec::new()));
+            }
+
+            let names = service_names(project)?
+                .into_iter()
+                .filter(|name| services.is_empty() || services.contains(name))
+                .collect();
+
+            Ok((project.dir_name.clone(), names))
+        },
+    );
+
+    let streams = pool::summarize(&parameters.projects, results)?
+        .into_iter()
+        .flat_map(|(dir_name, names)| {
+            let project = &parameters.projects[&dir_name];
+            names
+                .into_iter()
+                .map(move |name| (project, format!("{}/{}", project.name(), name), name))
+        })
+        .collect::<Vec<_>>();
+
+    if streams.is_empty() {
+        bail!("No services to show logs for");
+    }
+
+    let width = streams
+        .iter()
+        .map(|(_, label, _)| label.len())
+        .max()
+        .unwrap_or_default();
+    let colors = [
+        Color::Cyan,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Red,
+    ];
+
+    let results = thread::scope(|scope| {
+        streams
+            .iter()
+            .enumerate()
+            .map(|(index, (project, label, service))| {
+                let prefix = style(format!("{:width$} | ", label))
+                    .fg(colors[index % colors.len()])
+                    .to_string();
+                let handle = scope.spawn(move || {
+                    cmd::set_prefix(Some(prefix));
+                    docker::compose(
+                        ComposeCmd::Logs(
+                            service.clone(),
+                            project.compose_files.clone(),
+                            options.clone(),
+                        ),
+                        &project.dir,
+                    )
+                });
+                (project.dir_name.clone(), handle)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(BTreeMap::new(), |mut results, (dir_name, handle)| {
+                let result = handle.join().unwrap().map(|_| ());
+                let entry = results.entry(dir_name).or_insert(Ok(()));
+                if entry.is_ok() {
+                    *entry = result;
+                }
+                results
             })
-            .collect();
+    });
+
+    pool::summarize(&parameters.projects, results).map(|_| ())
+}
+
+/// The services in the compose files of a project
+pub fn service_names(project: &Project) -> Result<Vec<String>> {
+    Ok(docker::compose(
+        ComposeCmd::Config(project.compose_files.clone()),
+        &project.dir,
+    )?
+    .stdout
+    .as_slice()
+    .lines()
+    .map_while(Result::ok)
+    .collect())
+}
+
+fn statuses(parameters: &Parameters) -> Result<BTreeMap<ProjectDirName, Vec<Service>>> {
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| Ok((project.dir_name.clone(), services(project)?)),
+    );
+
+    Ok(pool::summarize(&parameters.projects, results)?
+        .into_iter()
+        .collect())
+}
+
+/// Every service of the compose file, down or not
+fn services(project: &Project) -> Result<Vec<Service>> {
+    if !project.dir.exists() {
+        return Ok(Vec::new());
+    }
 
-            (dir_name.clone(), services)
+    let mut statuses: BTreeMap<String, Service> = service::parse_list(
+        &docker::compose(
+            ComposeCmd::List(project.compose_files.clone()),
+            &project.dir,
+        )?
+        .stdout,
+    )
+    .into_iter()
+    .map(|service| (service.name.clone(), service))
+    .collect();
+
+    Ok(service_names(project)?
+        .into_iter()
+        .map(|service_name| {
+            statuses
+                .remove(&*service_name)
+                .unwrap_or_else(|| Service::down(service_name))
         })
-        .collect()
+        .collect())
+}
+
+/// Run the commands of a hook, the failing step is added to any error so
+/// the summary shows where a project failed
+fn run_hook(app: &App, project: &Project, hook: Hook) -> Result<()> {
+    // before_install runs before there is a project directory
+    let dir = match hook {
+        Hook::BeforeInstall if !project.dir.exists() => app.config.shared_dir.as_path(),
+        _ => project.dir.as_path(),
+    };
+
+    let env = project_env(app, project)
+        .into_iter()
+        .chain([("DOCAT_HOOK".to_string(), hook.to_string())])
+        .collect();
+
+    cmd::run_from_list(
+        project.hook(hook),
+        project.continue_on_error.contains(&hook),
+        |command| command.build(project.shell.as_deref(), dir, &env),
+    )
+    .with_context(|| hook.to_string())?;
+
+    Ok(())
+}
+
+/// Variables that tell hooks and tasks which project they run for
+fn project_env(app: &App, project: &Project) -> BTreeMap<String, String> {
+    [
+        ("DOCAT_APP", app.name.clone()),
+        ("DOCAT_PROJECT", project.name()),
+        ("DOCAT_PROJECT_DIR", project.dir.display().to_string()),
+        ("DOCAT_SHARED_NETWORK", app.config.shared_network.clone()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
 }
 
-fn set_git_config(project: &mut Project, dir_name: &str) {
-    let default_repo_string = format!("https://git@github.com:name/{}.git", dir_name.to_string());
-    project.git = git::config(ConfigCmd::Get("remote.origin.url".to_string()), &cwd())
+fn create_networks_and_volumes(parameters: &Parameters, project: &Project) -> Result<()> {
+    docker::network(NetworkCmd::Create(
+        parameters.app.config.shared_network.clone(),
+    ))?;
+
+    project
+        .networks
+        .iter()
+        .try_for_each(|network| docker::network(NetworkCmd::Create(network.clone())).map(|_| ()))?;
+
+    project
+        .volumes
+        .iter()
+        .try_for_each(|volume| docker::volume(VolumeCmd::Create(volume.clone())).map(|_| ()))
+}
+
+fn with_dependencies(parameters: &Parameters) -> Result<Parameters> {
+    Ok(Parameters {
+        app: parameters.app.clone(),
+        projects: dependency::resolve(&parameters.app, &parameters.projects)?,
+        services: parameters.services.clone(),
+        jobs: parameters.jobs,
+    })
+}
+
+fn set_git_config(project: &mut Project, dir_name: &str) -> Result<()> {
+    let default_repo_string = format!("https://git@github.com:name/{}.git", dir_name);
+    project.git = git::config(ConfigCmd::Get("remote.origin.url".to_string()), &cwd()?)?
         .stdout
         .as_slice()
         .lines()
-        .filter_map(|x| x.ok())
+        .map_while(Result::ok)
         .fold(default_repo_string, |acc: String, line| {
             match line.is_empty() {
                 true => acc,
                 false => line,
             }
         });
+
+    Ok(())
 }
@@ -1,82 +1,110 @@
-use crate::{cmd, file};
-use std::path::PathBuf;
+use crate::runtime;
+use anyhow::Result;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
 pub enum NetworkCmd {
     Create(String),
+    Remove(String),
 }
 
 pub enum VolumeCmd {
     Create(String),
+    Remove(String),
+    /// Names of the volumes with a label, like `key=value`
+    List(String),
+    /// Write the contents of a volume to a tarball
+    Archive(String, PathBuf),
+    /// Replace the contents of a volume with those of a tarball
+    Restore(String, PathBuf),
+    /// IDs of the running containers that mount a volume
+    Users(String),
+}
+
+pub enum ContainerCmd {
+    Stop(Vec<String>),
+    Start(Vec<String>),
 }
 
 pub enum ComposeCmd {
     Up(Vec<String>, Vec<String>),
     Config(Vec<String>),
     List(Vec<String>),
-    Down,
+    Down(Vec<String>),
+    /// Down that also removes the volumes of the compose file
+    Destroy(Vec<String>),
+    Stop(Vec<String>, Vec<String>),
+    Start(Vec<String>, Vec<String>),
+    Rm(Vec<String>, Vec<String>),
     Run(String, Vec<String>, Vec<String>),
     Exec(String, Vec<String>, Vec<String>),
+    Logs(String, Vec<String>, LogOptions),
 }
 
-pub fn network(subcommand: NetworkCmd) -> Output {
-    let mut cmd_wrapper = cmd::new("docker", &file::cwd());
-    cmd_wrapper.ignore_error = true;
-    let cmd = &mut cmd_wrapper.command;
-    cmd.arg("network");
+#[derive(Clone, Default)]
+pub struct LogOptions {
+    pub follow: bool,
+    pub since: Option<String>,
+    pub tail: Option<String>,
+}
 
-    match subcommand {
-        NetworkCmd::Create(name) => cmd.arg("create").arg(name),
-    };
+pub fn network(subcommand: NetworkCmd) -> Result<Output> {
+    runtime::get().network(subcommand)
+}
 
-    cmd::run(cmd_wrapper).expect("Could not create docker network")
+pub fn volume(subcommand: VolumeCmd) -> Result<Output> {
+    runtime::get().volume(subcommand)
 }
 
-pub fn volume(subcommand: VolumeCmd) -> Output {
-    let mut cmd_wrapper = cmd::new("docker", &file::cwd());
-    cmd_wrapper.ignore_output = true;
-    let cmd = &mut cmd_wrapper.command;
-    cmd.arg("volume");
+pub fn container(subcommand: ContainerCmd) -> Result<Output> {
+    runtime::get().container(subcommand)
+}
 
-    match subcommand {
-        VolumeCmd::Create(volume) => cmd.args(["create", &volume[..]]),
-    };
+pub fn compose(subcommand: ComposeCmd, dir: &Path) -> Result<Output> {
+    runtime::get().compose(subcommand, dir)
+}
 
-    cmd::run(cmd_wrapper).expect("Could not create docker volume")
+/// The name compose gives a project in a directory, which its containers
+/// and volumes are labelled with
+pub fn project_name(dir_name: &str) -> String {
+    dir_name
+        .to_lowercase()
+        .chars()
+        .filter(|char| char.is_ascii_alphanumeric() || *char == '-' || *char == '_')
+        .collect()
 }
 
-pub fn compose(subcommand: ComposeCmd, dir: &PathBuf) -> Output {
-    let mut cmd_wrapper = cmd::new("docker", dir);
-    let cmd = &mut cmd_wrapper.command;
-    cmd.arg("compose");
+/// The volumes compose created for the project
+pub fn compose_volumes(dir_name: &str) -> Result<Vec<String>> {
+    let label = format!("com.docker.compose.project={}", project_name(dir_name));
+
+    Ok(volume(VolumeCmd::List(label))?
+        .stdout
+        .lines()
+        .map_while(Result::ok)
+        .filter(|volume| !volume.is_empty())
+        .collect())
+}
 
-    match subcommand {
-        ComposeCmd::Up(services, files) => {
-            add_files(cmd, files);
-            cmd.args(["up", "-d"]).args(services)
-        }
-        ComposeCmd::Down => cmd.arg("down"),
-        ComposeCmd::Config(files) => {
-            cmd_wrapper.ignore_output = true;
-            add_files(cmd, files);
-            cmd.args(["config", "--services"])
-        }
-        ComposeCmd::List(files) => {
-            cmd_wrapper.ignore_output = true;
-            add_files(cmd, files);
-            cmd.args(["ps", "--format", "json"])
-        }
-        ComposeCmd::Run(service, files, command_string) => {
-            add_files(cmd, files);
-            cmd.args(["run", "-T", "--rm", "--no-deps"]).arg(service).args(command_string)
-        }
-        ComposeCmd::Exec(service, files, command_string) => {
-            add_files(cmd, files);
-            cmd.args(["exec", "-T"]).arg(service).args(command_string)
-        }
-    };
+/// IDs of the running containers that mount any of the volumes, whichever
+/// project they belong to
+pub fn volume_users(volumes: &[String]) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for name in volumes {
+        volume(VolumeCmd::Users(name.clone()))?
+            .stdout
+            .lines()
+            .map_while(Result::ok)
+            .filter(|id| !id.is_empty())
+            .for_each(|id| {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            });
+    }
 
-    cmd::run(cmd_wrapper).expect("Could not start project")
+    Ok(ids)
 }
 
 pub fn add_files(cmd: &mut Command, files: Vec<String>) {
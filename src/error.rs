@@ -0,0 +1,111 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+
+/// Errors that scripts may want to tell apart, each with its own exit code.
+/// Anything else exits with 1.
+#[derive(Debug)]
+pub enum DocatError {
+    ConfigNotFound(PathBuf),
+    InvalidConfig(PathBuf, String),
+    UnknownApp(String),
+    AppNotDetermined,
+    UnknownProject(String),
+    ProjectNotDetermined,
+    DependencyCycle(Vec<String>),
+    ProgramNotFound(String),
+    CommandFailed {
+        command: String,
+        code: Option<i32>,
+    },
+    /// Carries the exit code of the failures when they all share one
+    ProjectsFailed {
+        failed: usize,
+        total: usize,
+        code: Option<i32>,
+    },
+    NoDirectory(&'static str),
+    /// The lock file no longer matches the config, with the reason why
+    StaleLock(String),
+    UndefinedVariable(String),
+    /// A command run in a container exited with this status
+    Exited(i32),
+}
+
+impl DocatError {
+    /// The exit code of the first docat error in the chain of causes
+    pub fn exit_code_of(err: &anyhow::Error) -> i32 {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<DocatError>())
+            .map_or(1, DocatError::exit_code)
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DocatError::ConfigNotFound(_) => 3,
+            DocatError::InvalidConfig(_, _) => 4,
+            DocatError::UnknownApp(_) | DocatError::AppNotDetermined => 5,
+            DocatError::UnknownProject(_) | DocatError::ProjectNotDetermined => 6,
+            DocatError::DependencyCycle(_) => 7,
+            DocatError::ProgramNotFound(_) => 8,
+            DocatError::CommandFailed { .. } => 9,
+            DocatError::ProjectsFailed { code, .. } => code.unwrap_or(10),
+            DocatError::NoDirectory(_) => 11,
+            DocatError::StaleLock(_) => 12,
+            DocatError::UndefinedVariable(_) => 13,
+            DocatError::Exited(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for DocatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DocatError::ConfigNotFound(path) => {
+                write!(f, "Could not find config file {}", path.display())
+            }
+            DocatError::InvalidConfig(path, reason) => {
+                write!(f, "Invalid config file {}: {}", path.display(), reason)
+            }
+            DocatError::UnknownApp(app) => write!(f, "Unknown app {}", app),
+            DocatError::AppNotDetermined => write!(
+                f,
+                "Could not determine app name, try passing it in with the --app flag"
+            ),
+            DocatError::UnknownProject(project) => write!(f, "Unknown project {}", project),
+            DocatError::ProjectNotDetermined => write!(
+                f,
+                "Could not determine project, consider passing the --project flag"
+            ),
+            DocatError::DependencyCycle(cycle) => {
+                write!(f, "Dependency cycle detected: {}", cycle.join(" -> "))
+            }
+            DocatError::ProgramNotFound(program) => {
+                write!(
+                    f,
+                    "Could not find {}, is it installed and on your PATH?",
+                    program
+                )
+            }
+            DocatError::CommandFailed { command, code } => match code {
+                Some(code) => write!(f, "`{}` exited with status {}", command, code),
+                None => write!(f, "`{}` was terminated by a signal", command),
+            },
+            DocatError::ProjectsFailed { failed, total, .. } => {
+                write!(f, "{} of {} projects failed", failed, total)
+            }
+            DocatError::NoDirectory(name) => write!(f, "Could not determine the {}", name),
+            DocatError::UndefinedVariable(name) => write!(
+                f,
+                "Variable {} is not defined, set it or give a default with ${{{}:-default}}",
+                name, name
+            ),
+            DocatError::Exited(code) => write!(f, "Command exited with status {}", code),
+            DocatError::StaleLock(reason) => {
+                write!(f, "Lock file is out of date, {}. Run docat lock", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DocatError {}
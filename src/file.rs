@@ -1,27 +1,65 @@
+use crate::error::DocatError;
+use anyhow::Result;
 use dirs::home_dir;
 use std::env::current_dir;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 pub const CONFIG_FILENAME: &str = "docat.yml";
+pub const LOCK_FILENAME: &str = "docat.lock";
 
-pub fn cwd() -> PathBuf {
-    match current_dir() {
-        Ok(path) => path,
-        Err(_) => panic!("Could not determine the cwd"),
-    }
+pub fn cwd() -> Result<PathBuf> {
+    current_dir().map_err(|_| DocatError::NoDirectory("current directory").into())
 }
 
-pub fn cached_config_path() -> PathBuf {
+pub fn cached_config_path() -> Result<PathBuf> {
     match home_dir() {
-        None => panic!("Could not load home directory"),
+        None => Err(DocatError::NoDirectory("home directory").into()),
         Some(mut file) => {
             file.push(".docat");
-            file
+            Ok(file)
+        }
+    }
+}
+
+/// Where uninstalled projects are moved to instead of being deleted
+pub fn trash_path() -> Result<PathBuf> {
+    let mut dir = cached_config_path()?;
+    dir.push("trash");
+    Ok(dir)
+}
+
+pub fn snapshots_path() -> Result<PathBuf> {
+    let mut dir = cached_config_path()?;
+    dir.push("snapshots");
+    Ok(dir)
+}
+
+/// Copy a directory with everything in it, keeping symlinks as they are
+pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+            #[cfg(not(unix))]
+            fs::copy(entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
         }
     }
+
+    Ok(())
 }
 
-pub fn cached_config_file() -> PathBuf {
-    let mut file = cached_config_path();
+pub fn cached_config_file() -> Result<PathBuf> {
+    let mut file = cached_config_path()?;
     file.push(CONFIG_FILENAME);
-    file
+    Ok(file)
 }
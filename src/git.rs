@@ -1,23 +1,110 @@
 use crate::cmd;
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io::BufRead;
+use std::path::Path;
 use std::process::Output;
 
 pub enum ConfigCmd {
     Get(String),
 }
 
-pub fn clone(repository: &String, directory: &PathBuf) -> Output {
+pub enum QueryCmd {
+    RevParse(String),
+    CurrentBranch,
+    Status,
+    AheadBehind,
+    StashList,
+    /// Commits on local branches that are on no remote
+    Unpushed,
+    IsRepository,
+    IsAncestor(String, String),
+}
+
+/// The state of a checkout shown by `docat git-status`
+pub enum RepoStatus {
+    NotInstalled,
+    NotARepository,
+    Repository {
+        branch: Option<String>,
+        head: String,
+        ahead_behind: Option<(usize, usize)>,
+        changes: usize,
+        stashes: usize,
+    },
+}
+
+impl RepoStatus {
+    pub fn of(directory: &Path) -> Result<Self> {
+        if !directory.exists() {
+            return Ok(RepoStatus::NotInstalled);
+        }
+
+        if !is_repository(directory)? {
+            return Ok(RepoStatus::NotARepository);
+        }
+
+        Ok(RepoStatus::Repository {
+            branch: current_branch(directory)?,
+            head: rev_parse("HEAD", directory)?,
+            ahead_behind: ahead_behind(directory)?,
+            changes: changes(directory)?,
+            stashes: stashes(directory)?,
+        })
+    }
+
+    /// The columns of the git-status table
+    pub fn columns(&self) -> Vec<String> {
+        match self {
+            RepoStatus::NotInstalled => vec!["not installed".to_string()],
+            RepoStatus::NotARepository => vec!["not a git repository".to_string()],
+            RepoStatus::Repository {
+                branch,
+                head,
+                ahead_behind,
+                changes,
+                stashes,
+            } => vec![
+                match branch {
+                    Some(branch) => branch.clone(),
+                    None => format!("detached at {}", short(head)),
+                },
+                match ahead_behind {
+                    None => "no upstream".to_string(),
+                    Some((0, 0)) => "up to date".to_string(),
+                    Some((ahead, behind)) => format!("ahead {}, behind {}", ahead, behind),
+                },
+                match changes {
+                    0 => "clean".to_string(),
+                    changes => format!("{} changed", changes),
+                },
+                match stashes {
+                    0 => String::new(),
+                    stashes => format!("{} stashed", stashes),
+                },
+            ],
+        }
+    }
+}
+
+pub fn clone(repository: &str, branch: &str, directory: &Path) -> Result<Output> {
     let mut cmd_wrapper = cmd::new("git", directory);
     let cmd = &mut cmd_wrapper.command;
     cmd.arg("clone").arg(repository);
+    if !branch.is_empty() {
+        cmd.args(["--branch", branch]);
+    }
 
-    cmd::run(cmd_wrapper).expect("Failed to clone repository.")
+    cmd::run_checked(cmd_wrapper).context(format!("Failed to clone {}", repository))
 }
 
-pub fn config(subcommand: ConfigCmd, directory: &PathBuf) -> Output {
+pub fn config(subcommand: ConfigCmd, directory: &Path) -> Result<Output> {
     let mut cmd_wrapper = cmd::new("git", directory);
     cmd_wrapper.ignore_output = true;
     cmd_wrapper.ignore_error = true;
+    cmd_wrapper.read_only = true;
     let cmd = &mut cmd_wrapper.command;
     cmd.arg("config");
 
@@ -25,5 +112,214 @@ pub fn config(subcommand: ConfigCmd, directory: &PathBuf) -> Output {
         ConfigCmd::Get(value) => cmd.arg("--get").arg(value),
     };
 
-    cmd::run(cmd_wrapper).expect("Could get get git config")
+    cmd::run(cmd_wrapper).context("Could not get git config")
+}
+
+/// Where a checkout is compared to the branch or ref it is pinned to
+#[derive(Serialize, PartialEq, Debug, Clone)]
+pub struct Checkout {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub head: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+    pub drifted: bool,
+}
+
+impl fmt::Display for Checkout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let branch = self.branch.as_deref().unwrap_or("detached");
+        write!(f, "{} {}", branch, short(&self.head))?;
+
+        match (&self.pinned, self.drifted) {
+            (Some(pinned), true) => write!(f, " (drifted from {})", pinned),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What `docat update` did to a checkout
+pub enum Update {
+    NotInstalled,
+    NotARepository,
+    UpToDate,
+    /// There are local commits that have not been pushed, nothing to pull
+    Ahead,
+    CheckedOut {
+        from: String,
+        to: String,
+    },
+    Updated {
+        from: String,
+        to: String,
+    },
+}
+
+impl Update {
+    /// Whether HEAD points to a different commit than before
+    pub fn moved(&self) -> bool {
+        matches!(self, Update::Updated { .. } | Update::CheckedOut { .. })
+    }
+}
+
+impl fmt::Display for Update {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Update::NotInstalled => write!(f, "not installed"),
+            Update::NotARepository => write!(f, "not a git repository"),
+            Update::UpToDate => write!(f, "up to date"),
+            Update::Ahead => write!(f, "ahead of upstream"),
+            Update::Updated { from, to } => write!(f, "updated {}..{}", short(from), short(to)),
+            Update::CheckedOut { from, to } => {
+                write!(f, "checked out {} (was {})", to, short(from))
+            }
+        }
+    }
+}
+
+pub fn fetch(directory: &Path) -> Result<Output> {
+    let mut cmd_wrapper = cmd::new("git", directory);
+    cmd_wrapper.ignore_output = true;
+    cmd_wrapper.ignore_error = true;
+    cmd_wrapper.command.args(["fetch", "--tags"]);
+
+    cmd::run_checked(cmd_wrapper).context("Failed to fetch")
+}
+
+/// Fast-forward the current branch to its upstream
+pub fn fast_forward(directory: &Path) -> Result<Output> {
+    let mut cmd_wrapper = cmd::new("git", directory);
+    cmd_wrapper.ignore_output = true;
+    cmd_wrapper.ignore_error = true;
+    cmd_wrapper
+        .command
+        .args(["merge", "--ff-only", "@{upstream}"]);
+
+    cmd::run_checked(cmd_wrapper).context("Failed to fast-forward")
+}
+
+/// Switch to a branch, or detach at a tag or commit
+pub fn checkout(revision: &str, detach: bool, directory: &Path) -> Result<Output> {
+    let mut cmd_wrapper = cmd::new("git", directory);
+    cmd_wrapper.ignore_output = true;
+    cmd_wrapper.ignore_error = true;
+    let cmd = &mut cmd_wrapper.command;
+    cmd.arg("checkout");
+    if detach {
+        cmd.arg("--detach");
+    }
+    cmd.arg(revision);
+
+    cmd::run_checked(cmd_wrapper).context(format!("Failed to check out {}", revision))
+}
+
+/// The branch that is checked out, if HEAD is not detached
+pub fn current_branch(directory: &Path) -> Result<Option<String>> {
+    let output = query(QueryCmd::CurrentBranch, directory)?;
+
+    Ok(output.status.success().then(|| stdout(&output)))
+}
+
+/// The commit a revision like `HEAD` points to
+pub fn rev_parse(revision: &str, directory: &Path) -> Result<String> {
+    let output = query(QueryCmd::RevParse(revision.to_string()), directory)?;
+    if !output.status.success() {
+        bail!("Could not resolve {}", revision);
+    }
+
+    Ok(stdout(&output))
+}
+
+/// The number of changed files, untracked files included
+pub fn changes(directory: &Path) -> Result<usize> {
+    let output = query(QueryCmd::Status, directory)?;
+    if !output.status.success() {
+        bail!("Could not get git status");
+    }
+
+    Ok(output.stdout.lines().count())
+}
+
+pub fn is_dirty(directory: &Path) -> Result<bool> {
+    Ok(changes(directory)? > 0)
+}
+
+/// How many commits HEAD is ahead of and behind its upstream, if it has one
+pub fn ahead_behind(directory: &Path) -> Result<Option<(usize, usize)>> {
+    let output = query(QueryCmd::AheadBehind, directory)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let counts = stdout(&output)
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    match counts.as_slice() {
+        [ahead, behind] => Ok(Some((*ahead, *behind))),
+        _ => bail!("Could not count commits against upstream"),
+    }
+}
+
+pub fn stashes(directory: &Path) -> Result<usize> {
+    Ok(query(QueryCmd::StashList, directory)?
+        .stdout
+        .lines()
+        .count())
+}
+
+/// The number of commits on local branches that have not been pushed anywhere
+pub fn unpushed(directory: &Path) -> Result<usize> {
+    let output = query(QueryCmd::Unpushed, directory)?;
+    if !output.status.success() {
+        bail!("Could not count unpushed commits");
+    }
+
+    Ok(stdout(&output).parse()?)
+}
+
+pub fn is_repository(directory: &Path) -> Result<bool> {
+    Ok(query(QueryCmd::IsRepository, directory)?.status.success())
+}
+
+pub fn is_ancestor(ancestor: &str, descendant: &str, directory: &Path) -> Result<bool> {
+    let subcommand = QueryCmd::IsAncestor(ancestor.to_string(), descendant.to_string());
+
+    Ok(query(subcommand, directory)?.status.success())
+}
+
+/// Run a command that only reads the repository. Its output is captured and
+/// it is up to the caller to check the exit status.
+pub fn query(subcommand: QueryCmd, directory: &Path) -> Result<Output> {
+    let mut cmd_wrapper = cmd::new("git", directory);
+    cmd_wrapper.ignore_output = true;
+    cmd_wrapper.ignore_error = true;
+    cmd_wrapper.read_only = true;
+    let cmd = &mut cmd_wrapper.command;
+
+    match subcommand {
+        QueryCmd::RevParse(revision) => cmd.args(["rev-parse", "--verify", "--quiet", &revision]),
+        QueryCmd::CurrentBranch => cmd.args(["symbolic-ref", "--quiet", "--short", "HEAD"]),
+        QueryCmd::Status => cmd.args(["status", "--porcelain"]),
+        QueryCmd::AheadBehind => {
+            cmd.args(["rev-list", "--left-right", "--count", "HEAD...@{upstream}"])
+        }
+        QueryCmd::StashList => cmd.args(["stash", "list"]),
+        QueryCmd::Unpushed => cmd.args(["rev-list", "--count", "--branches", "--not", "--remotes"]),
+        QueryCmd::IsRepository => cmd.args(["rev-parse", "--is-inside-work-tree"]),
+        QueryCmd::IsAncestor(ancestor, descendant) => {
+            cmd.args(["merge-base", "--is-ancestor", &ancestor, &descendant])
+        }
+    };
+
+    cmd::run(cmd_wrapper)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
 }
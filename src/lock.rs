@@ -0,0 +1,106 @@
+use crate::config::app::App;
+use crate::error::DocatError;
+use crate::file::LOCK_FILENAME;
+use crate::{git, ProjectDirName};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The commit every project of an app was on when `docat lock` ran
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Lock {
+    pub projects: BTreeMap<ProjectDirName, LockedProject>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct LockedProject {
+    pub git: String,
+    /// The branch or ref the project was pinned to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+    pub commit: String,
+}
+
+/// The lock file lives next to the docat.yml of the install project
+pub fn path(app: &App) -> PathBuf {
+    app.config.install_dir.join(LOCK_FILENAME)
+}
+
+/// Resolve the commit of every project with a git repository
+pub fn create(app: &App) -> Result<Lock> {
+    let projects = app
+        .projects
+        .iter()
+        .filter(|(_, project)| !project.git.is_empty())
+        .map(|(dir_name, project)| {
+            if !project.dir.exists() {
+                bail!(
+                    "{} is not installed, run docat install first",
+                    project.name()
+                );
+            }
+
+            let locked = LockedProject {
+                git: project.git.clone(),
+                pinned: project.pinned().map(str::to_string),
+                commit: git::rev_parse("HEAD", &project.dir)
+                    .with_context(|| format!("Could not lock {}", project.name()))?,
+            };
+
+            Ok((dir_name.clone(), locked))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Lock { projects })
+}
+
+pub fn save(app: &App, lock: &Lock) -> Result<PathBuf> {
+    let path = path(app);
+    fs::write(&path, serde_yaml::to_string(lock)?)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Load the lock file and make sure it still matches the config
+pub fn load(app: &App) -> Result<Lock> {
+    let path = path(app);
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Could not read {}, run docat lock first", path.display()))?;
+    let lock: Lock = serde_yaml::from_str(&contents)
+        .map_err(|err| DocatError::InvalidConfig(path.clone(), err.to_string()))?;
+
+    if let Some(reason) = stale(app, &lock) {
+        bail!(DocatError::StaleLock(reason));
+    }
+
+    Ok(lock)
+}
+
+/// Why the lock no longer describes the projects in the config, if it does not
+fn stale(app: &App, lock: &Lock) -> Option<String> {
+    let added = app
+        .projects
+        .iter()
+        .filter(|(_, project)| !project.git.is_empty())
+        .find(|(dir_name, _)| !lock.projects.contains_key(*dir_name))
+        .map(|(_, project)| format!("{} is not locked", project.name()));
+
+    let changed = lock.projects.iter().find_map(|(dir_name, locked)| {
+        let project = match app.projects.get(dir_name) {
+            Some(project) if !project.git.is_empty() => project,
+            _ => return Some(format!("{} is no longer in the config", dir_name)),
+        };
+
+        if project.git != locked.git {
+            return Some(format!("the repository of {} changed", project.name()));
+        }
+
+        (project.pinned() != locked.pinned.as_deref())
+            .then(|| format!("the pinned revision of {} changed", project.name()))
+    });
+
+    added.or(changed)
+}
@@ -2,20 +2,29 @@ extern crate core;
 
 mod cmd;
 mod config;
+mod dependency;
 mod docat;
 mod docker;
+mod error;
 mod file;
 mod git;
+mod lock;
+mod pool;
+mod ready;
+mod runtime;
 mod service;
+mod snapshot;
 
 use crate::config::project::Project;
-use crate::docker::{ComposeCmd, NetworkCmd, VolumeCmd};
+use crate::docker::{ComposeCmd, LogOptions, NetworkCmd, VolumeCmd};
+use crate::error::DocatError;
 use crate::file::cwd;
 use anyhow::{bail, Result};
 use clap::Parser;
 use config::app::App;
 use std::collections::BTreeMap;
-use std::fs;
+use std::path::PathBuf;
+use std::{fs, process};
 
 type ProjectDirName = String;
 
@@ -32,6 +41,14 @@ pub struct Args {
     /// Run on all projects
     #[clap(global = true, long, default_missing_value = "true")]
     all: Option<bool>,
+
+    /// Number of projects to run at the same time
+    #[clap(global = true, long, short, default_value_t = 1)]
+    jobs: usize,
+
+    /// Print the commands that would be run instead of running them
+    #[clap(global = true, long)]
+    dry_run: bool,
 }
 
 #[derive(clap::Subcommand, Clone)]
@@ -45,7 +62,12 @@ enum Command {
     Install {
         /// List of projects to install
         projects: Vec<String>,
+        /// Check out the commits in docat.lock
+        #[clap(long)]
+        locked: bool,
     },
+    /// Record the commit of every project in docat.lock
+    Lock,
     /// Re-run commands on install
     RunInstall {
         /// List of projects to install
@@ -53,23 +75,116 @@ enum Command {
     },
     /// Bring up projects
     Up {
-        /// List of projects to bring up
+        /// List of projects to bring up, optionally with services like api:web,worker
         projects: Vec<String>,
+        /// Only bring up these services of every project
+        #[clap(long = "service", short)]
+        services: Vec<String>,
     },
     /// Bring down projects
     Down {
-        /// List of projects to bring down
+        /// List of projects to bring down, optionally with services like api:web,worker
+        projects: Vec<String>,
+        /// Only stop and remove these services of every project
+        #[clap(long = "service", short)]
+        services: Vec<String>,
+    },
+    /// Bring projects down and remove their directories. Projects the
+    /// install project lists are cloned again by the next install or up.
+    Uninstall {
+        /// List of projects to uninstall
+        #[clap(required = true)]
         projects: Vec<String>,
+        /// Move the directories to ~/.docat/trash instead of deleting them
+        #[clap(long)]
+        trash: bool,
+        /// Remove the directories even if they have work that is not pushed
+        #[clap(long)]
+        force: bool,
+    },
+    /// Remove the containers, volumes and networks of every project
+    Destroy {
+        /// Do not ask for confirmation
+        #[clap(long, short)]
+        yes: bool,
     },
     /// Restart projects
     Restart {
-        /// List of projects to restart
+        /// List of projects to restart, optionally with services like api:web,worker
         projects: Vec<String>,
+        /// Only restart these services of every project
+        #[clap(long = "service", short)]
+        services: Vec<String>,
+    },
+    /// Pull the latest changes of project repos
+    Update {
+        /// List of projects to update
+        projects: Vec<String>,
+        /// Re-run commands on install for projects that changed
+        #[clap(long)]
+        run_install: bool,
     },
     /// Get status for projects
     Status {
         /// List of projects to get status for
         projects: Vec<String>,
+        /// How to print the status
+        #[clap(arg_enum, long, default_value = "table")]
+        format: Format,
+        /// Show the checked out branch and whether it drifted from the pinned one
+        #[clap(long)]
+        git: bool,
+    },
+    /// Show logs of projects
+    Logs {
+        /// List of projects to show logs for
+        projects: Vec<String>,
+        /// Only show logs of these services
+        #[clap(long = "service", short)]
+        services: Vec<String>,
+        /// Keep streaming new logs
+        #[clap(long, short)]
+        follow: bool,
+        /// Show logs since a timestamp or a relative time like 10m
+        #[clap(long)]
+        since: Option<String>,
+        /// Number of lines to show from the end of the logs
+        #[clap(long)]
+        tail: Option<String>,
+    },
+    /// Run a command in the directory of every project
+    Foreach {
+        /// List of projects to run the command in
+        projects: Vec<String>,
+        /// The command to run, after --
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Run a task defined in the project config
+    Task {
+        /// The task to run
+        #[clap(required_unless_present = "list")]
+        name: Option<String>,
+        /// List of projects to run the task on, defaults to every project that defines it
+        projects: Vec<String>,
+        /// Show the tasks every project defines
+        #[clap(long, conflicts_with = "name")]
+        list: bool,
+    },
+    /// Check docat.yml files or print their schema
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Save and restore the volumes of projects
+    Snapshot {
+        #[clap(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Get branch, upstream, changes and stashes of project repos
+    GitStatus {
+        /// List of projects to get the git status for
+        projects: Vec<String>,
     },
     /// Start a new container without dependencies and run command
     Run {
@@ -93,55 +208,202 @@ enum Command {
     },
 }
 
+#[derive(clap::Subcommand, Clone)]
+enum ConfigCommand {
+    /// Check a docat.yml for unknown keys, missing compose files, git urls
+    /// and install projects
+    Validate {
+        /// Directory of the docat.yml, defaults to the current directory
+        dir: Option<PathBuf>,
+    },
+    /// Print the JSON Schema of docat.yml, for editors to complete and check it
+    Schema,
+}
+
+#[derive(clap::Subcommand, Clone)]
+enum SnapshotCommand {
+    /// Archive the volumes of projects to ~/.docat/snapshots
+    Save {
+        /// Name of the snapshot
+        name: String,
+        /// List of projects to save the volumes of
+        projects: Vec<String>,
+    },
+    /// Replace the volumes of projects with the ones in a snapshot
+    Restore {
+        /// Name of the snapshot
+        name: String,
+    },
+    /// List the snapshots of the app
+    List,
+    /// Remove a snapshot
+    Rm {
+        /// Name of the snapshot
+        name: String,
+    },
+}
+
+#[derive(clap::ArgEnum, Clone, Copy)]
+pub enum Format {
+    Table,
+    Json,
+    Yaml,
+}
+
 #[derive(Clone)]
 pub struct Parameters {
     pub app: App,
     pub projects: BTreeMap<ProjectDirName, Project>,
+    /// Services asked for per project, projects without any use all of them
+    pub services: BTreeMap<ProjectDirName, Vec<String>>,
+    pub jobs: usize,
+}
+
+impl Parameters {
+    pub fn services(&self, dir_name: &str) -> Vec<String> {
+        self.services.get(dir_name).cloned().unwrap_or_default()
+    }
 }
 
 fn get_app(args: &Args) -> Result<App> {
-    config::combine(&args.app)
+    let app = config::combine(&args.app)?;
+    runtime::select(app.config.runtime);
+
+    Ok(app)
 }
 
-fn main() -> Result<()> {
-    let mut args = Args::parse();
+fn main() {
+    if let Err(err) = run(Args::parse()) {
+        eprintln!("Error: {:#}", err);
+
+        process::exit(DocatError::exit_code_of(&err));
+    }
+}
 
-    fs::create_dir_all(file::cached_config_path())?;
+fn run(mut args: Args) -> Result<()> {
+    cmd::set_dry_run(args.dry_run);
+    if !args.dry_run {
+        fs::create_dir_all(file::cached_config_path()?)?;
+    }
 
     match args.clone().command {
         Command::Init { app } => docat::init(app)?,
-        Command::Install { projects } => docat::install(&get_parameters(&args, &projects, false)?),
+        Command::Install { projects, locked } => {
+            docat::install(&get_parameters(&args, &projects, false)?, locked)?
+        }
+        Command::Lock => docat::lock(&get_app(&args)?)?,
         Command::RunInstall { projects } => {
-            docat::run_install(&get_parameters(&args, &projects, false)?)
+            docat::run_install(&get_parameters(&args, &projects, false)?)?
+        }
+        Command::Up { projects, services } => {
+            docat::up(&get_service_parameters(&args, &projects, &services)?)?
         }
-        Command::Up { projects } => {
-            docat::up(&get_parameters(&args, &projects, projects.is_empty())?)
+        Command::Down { projects, services } => {
+            docat::down(&get_service_parameters(&args, &projects, &services)?)?
         }
-        Command::Down { projects } => {
-            docat::down(&get_parameters(&args, &projects, projects.is_empty())?)
+        Command::Uninstall {
+            projects,
+            trash,
+            force,
+        } => docat::uninstall(&get_parameters(&args, &projects, false)?, trash, force)?,
+        Command::Destroy { yes } => {
+            args.all = Some(true);
+            docat::destroy(&get_parameters(&args, &[], false)?, yes)?
         }
-        Command::Restart { projects } => {
-            docat::restart(&get_parameters(&args, &projects, projects.is_empty())?)
+        Command::Restart { projects, services } => {
+            docat::restart(&get_service_parameters(&args, &projects, &services)?)?
         }
-        Command::Status { projects } => {
+        Command::Update {
+            projects,
+            run_install,
+        } => docat::update(
+            &get_parameters(&args, &projects, projects.is_empty())?,
+            run_install,
+        )?,
+        Command::Status {
+            projects,
+            format,
+            git,
+        } => {
+            if projects.is_empty() {
+                args.all = Some(true);
+            }
+            docat::status(&get_parameters(&args, &projects, false)?, format, git)?
+        }
+        Command::Foreach { projects, command } => {
+            if projects.is_empty() {
+                args.all = Some(true);
+            }
+            docat::foreach(&get_parameters(&args, &projects, false)?, &command)?
+        }
+        Command::Task { name, projects, .. } => match name {
+            Some(name) => {
+                let selected = !projects.is_empty();
+                if !selected {
+                    args.all = Some(true);
+                }
+                docat::task(&get_parameters(&args, &projects, false)?, &name, selected)?
+            }
+            None => docat::list_tasks(&get_app(&args)?),
+        },
+        Command::Config { command } => match command {
+            ConfigCommand::Validate { dir } => docat::validate_config(&match dir {
+                Some(dir) => dir,
+                None => cwd()?,
+            })?,
+            ConfigCommand::Schema => docat::config_schema()?,
+        },
+        Command::Snapshot { command } => match command {
+            SnapshotCommand::Save { name, projects } => {
+                if projects.is_empty() {
+                    args.all = Some(true);
+                }
+                docat::snapshot_save(&get_parameters(&args, &projects, false)?, &name)?
+            }
+            SnapshotCommand::Restore { name } => {
+                args.all = Some(true);
+                docat::snapshot_restore(&get_parameters(&args, &[], false)?, &name)?
+            }
+            SnapshotCommand::List => docat::snapshot_list(&get_app(&args)?)?,
+            SnapshotCommand::Rm { name } => docat::snapshot_rm(&get_app(&args)?, &name)?,
+        },
+        Command::GitStatus { projects } => {
             if projects.is_empty() {
                 args.all = Some(true);
             }
-            docat::status(&get_parameters(&args, &projects, false)?)
+            docat::git_status(&get_parameters(&args, &projects, false)?)?
+        }
+        Command::Logs {
+            projects,
+            services,
+            follow,
+            since,
+            tail,
+        } => {
+            let options = LogOptions {
+                follow,
+                since,
+                tail,
+            };
+            docat::logs(
+                &get_parameters(&args, &projects, false)?,
+                &services,
+                &options,
+            )?
         }
         Command::Run {
             service,
             command,
             project,
         } => {
-            docat::run(&service, &command, &get_project(&args, project)?);
+            docat::run(&service, &command, &get_project(&args, project)?)?;
         }
         Command::Exec {
             service,
             command,
             project,
         } => {
-            docat::exec(&service, &command, &get_project(&args, project)?);
+            docat::exec(&service, &command, &get_project(&args, project)?)?;
         }
     };
 
@@ -150,18 +412,101 @@ fn main() -> Result<()> {
 
 fn get_parameters(
     args: &Args,
-    project_names: &Vec<String>,
+    project_names: &[String],
     include_install: bool,
 ) -> Result<Parameters> {
     let app = get_app(args)?;
     let projects = get_projects(&app, project_names, args.all, include_install)?;
 
-    Ok(Parameters { app, projects })
+    Ok(Parameters {
+        app,
+        projects,
+        services: BTreeMap::new(),
+        jobs: args.jobs,
+    })
+}
+
+/// Parameters for project arguments that may name services, like
+/// `api:web,worker`, with the services passed to every project added
+fn get_service_parameters(
+    args: &Args,
+    project_args: &[String],
+    services: &[String],
+) -> Result<Parameters> {
+    let project_services = project_args
+        .iter()
+        .map(|project_arg| match project_arg.split_once(':') {
+            Some((name, services)) => (
+                name.to_string(),
+                services
+                    .split(',')
+                    .filter(|service| !service.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            None => (project_arg.clone(), Vec::new()),
+        })
+        .collect::<Vec<(String, Vec<String>)>>();
+    let project_names = project_services
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+
+    let mut parameters = get_parameters(args, &project_names, project_names.is_empty())?;
+
+    // --service only applies to the projects that define the service
+    let results = pool::run(
+        &parameters.projects,
+        BTreeMap::new(),
+        parameters.jobs,
+        |project| {
+            let named = project_services
+                .iter()
+                .filter(|(name, _)| *name == project.dir_name || *name == project.name())
+                .flat_map(|(_, services)| services.clone())
+                .collect::<Vec<_>>();
+            let defined = match services.is_empty() || !project.dir.exists() {
+                true => Vec::new(),
+                false => docat::service_names(project)?,
+            };
+            let shared = services
+                .iter()
+                .filter(|service| defined.contains(service))
+                .cloned();
+
+            Ok((
+                project.dir_name.clone(),
+                named.into_iter().chain(shared).collect(),
+            ))
+        },
+    );
+    let project_services: BTreeMap<ProjectDirName, Vec<String>> =
+        pool::summarize(&parameters.projects, results)?
+            .into_iter()
+            .collect();
+
+    if !services.is_empty() {
+        parameters
+            .projects
+            .retain(|dir_name, _| !project_services[dir_name].is_empty());
+        if parameters.projects.is_empty() {
+            bail!(
+                "None of the projects has a service {}",
+                services.join(" or ")
+            );
+        }
+    }
+    parameters.services = project_services
+        .into_iter()
+        .filter(|(_, services)| !services.is_empty())
+        .collect();
+
+    Ok(parameters)
 }
 
 fn get_projects(
     app: &App,
-    project_names: &Vec<String>,
+    project_names: &[String],
     all: Option<bool>,
     mut include_install: bool,
 ) -> Result<BTreeMap<ProjectDirName, Project>> {
@@ -174,10 +519,10 @@ fn get_projects(
     }
 
     if !project_names.is_empty() {
-        return filter_projects(&app, project_names, include_install);
+        return filter_projects(app, project_names, include_install);
     }
 
-    let cwd = cwd();
+    let cwd = cwd()?;
     let mut projects = app
         .projects
         .iter()
@@ -196,17 +541,25 @@ fn get_projects(
         include_install = false;
     }
 
-    filter_projects(&app, &projects, include_install)
+    filter_projects(app, &projects, include_install)
 }
 
 fn filter_projects(
     app: &App,
-    projects: &Vec<String>,
+    projects: &[String],
     include_install: bool,
 ) -> Result<BTreeMap<ProjectDirName, Project>> {
+    if let Some(unknown) = projects.iter().find(|dir_or_project_name| {
+        !app.projects.iter().any(|(dir_name, project)| {
+            dir_name == *dir_or_project_name || project.name() == **dir_or_project_name
+        })
+    }) {
+        bail!(DocatError::UnknownProject(unknown.clone()));
+    }
+
     let mut cloned_projects = app.projects.clone();
     let mut projects = projects
-        .into_iter()
+        .iter()
         .filter_map(|dir_or_project_name| {
             cloned_projects
                 .remove(dir_or_project_name)
@@ -234,7 +587,7 @@ fn filter_projects(
 
 fn get_project(args: &Args, project_name: Option<String>) -> Result<Project> {
     let app = get_app(args)?;
-    let dir = cwd();
+    let dir = cwd()?;
 
     app.projects
         .iter()
@@ -249,7 +602,5 @@ fn get_project(args: &Args, project_name: Option<String>) -> Result<Project> {
                 .find(|(_, project)| project.dir == dir)
                 .map(|tuple| tuple.1.clone())
         })
-        .ok_or(anyhow::anyhow!(
-            "Could not determine project, consider passing the --project flag"
-        ))
+        .ok_or_else(|| DocatError::ProjectNotDetermined.into())
 }
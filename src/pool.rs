@@ -0,0 +1,200 @@
+use crate::error::DocatError;
+use crate::{cmd, Project, ProjectDirName};
+use anyhow::{anyhow, bail, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+struct State {
+    waiting: BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>>,
+    succeeded: BTreeSet<ProjectDirName>,
+    failed: BTreeSet<ProjectDirName>,
+    running: usize,
+}
+
+/// Run a task for every project using at most `jobs` threads. A project is
+/// only started once every project it waits on has succeeded, and is skipped
+/// if one of them failed.
+pub fn run<T, F>(
+    projects: &BTreeMap<ProjectDirName, Project>,
+    waits_on: BTreeMap<ProjectDirName, BTreeSet<ProjectDirName>>,
+    jobs: usize,
+    task: F,
+) -> BTreeMap<ProjectDirName, Result<T>>
+where
+    T: Send,
+    F: Fn(&Project) -> Result<T> + Sync,
+{
+    let width = projects
+        .values()
+        .map(|project| project.name().len())
+        .max()
+        .unwrap_or_default();
+    let state = Mutex::new(State {
+        waiting: projects
+            .keys()
+            .map(|dir_name| {
+                let dependencies = waits_on.get(dir_name).cloned().unwrap_or_default();
+                (dir_name.clone(), dependencies)
+            })
+            .collect(),
+        succeeded: BTreeSet::new(),
+        failed: BTreeSet::new(),
+        running: 0,
+    });
+    let changed = Condvar::new();
+    let results = Mutex::new(BTreeMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(projects.len()) {
+            scope.spawn(|| loop {
+                let dir_name = {
+                    let mut state = state.lock().unwrap();
+                    loop {
+                        if let Some((dir_name, skipped_by)) = next(&state) {
+                            state.waiting.remove(&dir_name);
+                            match skipped_by {
+                                Some(dependency) => {
+                                    state.failed.insert(dir_name.clone());
+                                    results.lock().unwrap().insert(
+                                        dir_name,
+                                        Err(anyhow!("Skipped, {} failed", dependency)),
+                                    );
+                                    changed.notify_all();
+                                    continue;
+                                }
+                                None => {
+                                    state.running += 1;
+                                    break Some(dir_name);
+                                }
+                            }
+                        }
+
+                        // nothing left that could become ready
+                        if state.waiting.is_empty() || state.running == 0 {
+                            break None;
+                        }
+
+                        state = changed.wait(state).unwrap();
+                    }
+                };
+
+                let dir_name = match dir_name {
+                    Some(dir_name) => dir_name,
+                    None => {
+                        changed.notify_all();
+                        break;
+                    }
+                };
+
+                let project = &projects[&dir_name];
+                if jobs > 1 {
+                    cmd::set_prefix(Some(format!("{:width$} | ", project.name())));
+                }
+                let result = task(project);
+                cmd::set_prefix(None);
+
+                let mut state = state.lock().unwrap();
+                state.running -= 1;
+                match result.is_ok() {
+                    true => state.succeeded.insert(dir_name.clone()),
+                    false => state.failed.insert(dir_name.clone()),
+                };
+                results.lock().unwrap().insert(dir_name, result);
+                changed.notify_all();
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+
+    // anything still waiting depends on itself through other projects
+    state
+        .into_inner()
+        .unwrap()
+        .waiting
+        .into_keys()
+        .for_each(|dir_name| {
+            results.insert(dir_name, Err(anyhow!("Skipped, dependency cycle")));
+        });
+
+    results
+}
+
+/// Print every failed project and fail if there were any
+pub fn summarize<T>(
+    projects: &BTreeMap<ProjectDirName, Project>,
+    results: BTreeMap<ProjectDirName, Result<T>>,
+) -> Result<Vec<T>> {
+    let mut values = Vec::new();
+    let mut failures = Vec::new();
+
+    results
+        .into_iter()
+        .for_each(|(dir_name, result)| match result {
+            Ok(value) => values.push(value),
+            Err(err) => failures.push((dir_name, err)),
+        });
+
+    if failures.is_empty() {
+        return Ok(values);
+    }
+
+    eprintln!();
+    failures.iter().for_each(|(dir_name, err)| {
+        let name = projects
+            .get(dir_name)
+            .map(|project| project.name())
+            .unwrap_or(dir_name.clone());
+        eprintln!("  {}  {:#}", name, err);
+    });
+
+    bail!(projects_failed(
+        failures.iter().map(|(_, err)| err),
+        projects.len()
+    ))
+}
+
+/// The error for the projects that failed out of `total`, which exits with
+/// the code of their errors when they all share one
+pub fn projects_failed<'a>(
+    errors: impl Iterator<Item = &'a anyhow::Error>,
+    total: usize,
+) -> DocatError {
+    let codes = errors.map(DocatError::exit_code_of).collect::<Vec<_>>();
+    let distinct = codes.iter().collect::<BTreeSet<_>>();
+
+    DocatError::ProjectsFailed {
+        failed: codes.len(),
+        total,
+        code: match distinct.len() {
+            1 => distinct.into_iter().next().copied(),
+            _ => None,
+        },
+    }
+}
+
+/// Find a project whose dependencies have all finished, along with the
+/// dependency that failed if it should be skipped
+fn next(state: &State) -> Option<(ProjectDirName, Option<ProjectDirName>)> {
+    state
+        .waiting
+        .iter()
+        .find_map(|(dir_name, dependencies)| {
+            dependencies
+                .iter()
+                .find(|dependency| state.failed.contains(*dependency))
+                .map(|dependency| (dir_name.clone(), Some(dependency.clone())))
+        })
+        .or_else(|| {
+            state
+                .waiting
+                .iter()
+                .find(|(_, dependencies)| {
+                    dependencies
+                        .iter()
+                        .all(|dependency| state.succeeded.contains(dependency))
+                })
+                .map(|(dir_name, _)| (dir_name.clone(), None))
+        })
+}
@@ -0,0 +1,133 @@
+use crate::config::readiness::Probe;
+use crate::docker::ComposeCmd;
+use crate::service::Health;
+use crate::{cmd, docker, service, Project};
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Wait until every service with a health check is healthy and every probe
+/// declared for the project passes. Command probes get the variables in `env`.
+pub fn wait(project: &Project, env: &BTreeMap<String, String>) -> Result<()> {
+    // nothing was started, so there is nothing to wait on
+    if cmd::is_dry_run() {
+        return Ok(());
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(project.ready.timeout());
+    let mut announced = false;
+
+    loop {
+        let reason = match pending(project, env)? {
+            None => return Ok(()),
+            Some(reason) => reason,
+        };
+
+        if Instant::now() >= deadline {
+            bail!(
+                "Timed out after {}s waiting for {}, {}",
+                project.ready.timeout(),
+                project.name(),
+                reason
+            );
+        }
+
+        if !announced {
+            println!("Waiting for {} to be ready", project.name());
+            announced = true;
+        }
+
+        sleep(INTERVAL);
+    }
+}
+
+/// Describe what the project is still waiting on, if anything
+fn pending(project: &Project, env: &BTreeMap<String, String>) -> Result<Option<String>> {
+    let services = service::parse_list(
+        &docker::compose(
+            ComposeCmd::List(project.compose_files.clone()),
+            &project.dir,
+        )?
+        .stdout,
+    );
+
+    if let Some(service) = services
+        .iter()
+        .find(|service| matches!(service.health, Health::Starting | Health::Unhealthy))
+    {
+        return Ok(Some(format!(
+            "service {} is {}",
+            service.name, service.health
+        )));
+    }
+
+    for probe in &project.ready.probes {
+        if !passes(probe, project, env)? {
+            return Ok(Some(format!("{} did not pass", describe(probe))));
+        }
+    }
+
+    Ok(None)
+}
+
+fn passes(probe: &Probe, project: &Project, env: &BTreeMap<String, String>) -> Result<bool> {
+    match probe {
+        Probe::Tcp(address) => Ok(connect(address).is_some()),
+        Probe::Http(url) => {
+            let (address, path) = match url.strip_prefix("http://") {
+                Some(rest) => rest.split_once('/').unwrap_or((rest, "")),
+                None => bail!("Only http:// urls can be probed, found {}", url),
+            };
+
+            // the Host header keeps the address as written, port or not
+            let has_port = address
+                .rsplit_once(':')
+                .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+            let socket_address = match has_port {
+                true => address.to_string(),
+                false => format!("{}:80", address),
+            };
+
+            Ok(connect(&socket_address)
+                .and_then(|mut stream| {
+                    let request = format!("GET /{} HTTP/1.0\r\nHost: {}\r\n\r\n", path, address);
+                    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).ok()?;
+                    stream.write_all(request.as_bytes()).ok()?;
+
+                    // only the status line is needed, e.g. "HTTP/1.1 200 OK"
+                    let mut response = [0; 12];
+                    stream.read_exact(&mut response).ok()?;
+                    String::from_utf8_lossy(&response[9..]).parse::<u16>().ok()
+                })
+                .is_some_and(|status| (200..400).contains(&status)))
+        }
+        Probe::Command(cmd_string) => Ok(cmd::from_string(cmd_string, &project.dir, env)?
+            .map(|mut cmd_wrapper| {
+                cmd_wrapper.ignore_output = true;
+                cmd_wrapper.ignore_error = true;
+                cmd::run(cmd_wrapper).is_ok_and(|output| output.status.success())
+            })
+            .unwrap_or(false)),
+    }
+}
+
+fn connect(address: &str) -> Option<TcpStream> {
+    address
+        .to_socket_addrs()
+        .ok()?
+        .find_map(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok())
+}
+
+fn describe(probe: &Probe) -> String {
+    match probe {
+        Probe::Tcp(address) => format!("tcp probe {}", address),
+        Probe::Http(url) => format!("http probe {}", url),
+        Probe::Command(cmd_string) => format!("command probe `{}`", cmd_string),
+    }
+}
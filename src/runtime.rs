@@ -0,0 +1,572 @@
+use crate::cmd::CommandWrapper;
+use crate::config::app_config::RuntimeKind;
+use crate::docker::{add_files, ComposeCmd, ContainerCmd, NetworkCmd, VolumeCmd};
+use crate::error::DocatError;
+use crate::{cmd, docker, file};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::env;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+static RUNTIME: OnceLock<Box<dyn ContainerRuntime>> = OnceLock::new();
+
+/// Image of the container that archives and restores volumes
+const HELPER_IMAGE: &str = "alpine:3";
+
+/// Labels compose puts on the containers it creates
+const PROJECT_LABEL: &str = "com.docker.compose.project";
+const SERVICE_LABEL: &str = "com.docker.compose.service";
+
+/// A container engine that can run compose projects and manage the
+/// networks and volumes they share
+pub trait ContainerRuntime: Send + Sync {
+    /// The program used for network and volume commands
+    fn program(&self) -> &str;
+
+    /// A command in `dir` that compose arguments can be added to
+    fn compose_command(&self, dir: &Path) -> CommandWrapper;
+
+    fn network(&self, subcommand: NetworkCmd) -> Result<Output> {
+        let mut cmd_wrapper = cmd::new(self.program(), &file::cwd()?);
+        cmd_wrapper.ignore_error = true;
+        let cmd = &mut cmd_wrapper.command;
+        cmd.arg("network");
+
+        let action = match subcommand {
+            NetworkCmd::Create(name) => {
+                cmd.arg("create").arg(name);
+                Action::Create
+            }
+            NetworkCmd::Remove(name) => {
+                cmd.arg("rm").arg(name);
+                Action::Remove
+            }
+        };
+
+        run_idempotent(cmd_wrapper, action).context(format!("Could not {} network", action))
+    }
+
+    fn volume(&self, subcommand: VolumeCmd) -> Result<Output> {
+        let mut cmd_wrapper = cmd::new(self.program(), &file::cwd()?);
+        cmd_wrapper.ignore_output = true;
+        cmd_wrapper.ignore_error = true;
+        cmd_wrapper.read_only = matches!(subcommand, VolumeCmd::List(_) | VolumeCmd::Users(_));
+        let cmd = &mut cmd_wrapper.command;
+
+        let action = match subcommand {
+            VolumeCmd::Create(volume) => {
+                cmd.args(["volume", "create", &volume[..]]);
+                Some(Action::Create)
+            }
+            VolumeCmd::Remove(volume) => {
+                cmd.args(["volume", "rm", &volume[..]]);
+                Some(Action::Remove)
+            }
+            VolumeCmd::List(label) => {
+                cmd.args(["volume", "ls", "--quiet", "--filter"])
+                    .arg(format!("label={}", label));
+                None
+            }
+            VolumeCmd::Archive(volume, archive) => {
+                add_tar(cmd, &volume, &archive, true);
+                None
+            }
+            VolumeCmd::Restore(volume, archive) => {
+                add_tar(cmd, &volume, &archive, false);
+                None
+            }
+            VolumeCmd::Users(volume) => {
+                cmd.args(["ps", "--quiet", "--filter"])
+                    .arg(format!("volume={}", volume));
+                None
+            }
+        };
+
+        match action {
+            Some(action) => {
+                run_idempotent(cmd_wrapper, action).context(format!("Could not {} volume", action))
+            }
+            None => cmd::run_checked(cmd_wrapper),
+        }
+    }
+
+    fn container(&self, subcommand: ContainerCmd) -> Result<Output> {
+        let mut cmd_wrapper = cmd::new(self.program(), &file::cwd()?);
+        cmd_wrapper.ignore_output = true;
+        let cmd = &mut cmd_wrapper.command;
+
+        match subcommand {
+            ContainerCmd::Stop(ids) => cmd.arg("stop").args(ids),
+            ContainerCmd::Start(ids) => cmd.arg("start").args(ids),
+        };
+
+        cmd::run_checked(cmd_wrapper)
+    }
+
+    fn compose(&self, subcommand: ComposeCmd, dir: &Path) -> Result<Output> {
+        run_compose(self.compose_command(dir), subcommand)
+    }
+}
+
+/// Docker with the compose v2 plugin
+pub struct DockerCompose;
+
+/// Docker with the standalone docker-compose v1 binary
+pub struct DockerComposeV1;
+
+pub struct PodmanCompose;
+
+pub struct NerdctlCompose;
+
+impl ContainerRuntime for DockerCompose {
+    fn program(&self) -> &str {
+        "docker"
+    }
+
+    fn compose_command(&self, dir: &Path) -> CommandWrapper {
+        let mut cmd_wrapper = cmd::new("docker", dir);
+        cmd_wrapper.command.arg("compose");
+        cmd_wrapper
+    }
+}
+
+impl ContainerRuntime for DockerComposeV1 {
+    fn program(&self) -> &str {
+        "docker"
+    }
+
+    fn compose_command(&self, dir: &Path) -> CommandWrapper {
+        cmd::new("docker-compose", dir)
+    }
+
+    fn compose(&self, subcommand: ComposeCmd, dir: &Path) -> Result<Output> {
+        let files = match subcommand {
+            ComposeCmd::List(files) => files,
+            subcommand => return run_compose(self.compose_command(dir), subcommand),
+        };
+
+        // v1 has no json output, so build it from the running services
+        let mut cmd_wrapper = self.compose_command(dir);
+        cmd_wrapper.ignore_output = true;
+        cmd_wrapper.read_only = true;
+        add_files(&mut cmd_wrapper.command, files);
+        cmd_wrapper
+            .command
+            .args(["ps", "--services", "--filter", "status=running"]);
+
+        let mut output = cmd::run_checked(cmd_wrapper)?;
+        let services = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|service| serde_json::json!({ "Service": service, "State": "running" }))
+            .collect::<Vec<_>>();
+        output.stdout = serde_json::to_vec(&services)?;
+
+        Ok(output)
+    }
+}
+
+impl ContainerRuntime for PodmanCompose {
+    fn program(&self) -> &str {
+        "podman"
+    }
+
+    fn compose_command(&self, dir: &Path) -> CommandWrapper {
+        let mut cmd_wrapper = cmd::new("podman", dir);
+        cmd_wrapper.command.arg("compose");
+        cmd_wrapper
+    }
+
+    fn compose(&self, subcommand: ComposeCmd, dir: &Path) -> Result<Output> {
+        if !matches!(subcommand, ComposeCmd::List(_)) {
+            return run_compose(self.compose_command(dir), subcommand);
+        }
+
+        // podman-compose hands ps to podman ps, which knows no services, so
+        // list the containers of the project and read the service from their
+        // labels
+        let dir_name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut cmd_wrapper = cmd::new(self.program(), dir);
+        cmd_wrapper.ignore_output = true;
+        cmd_wrapper.read_only = true;
+        cmd_wrapper
+            .command
+            .args(["ps", "--all", "--format", "json", "--filter"])
+            .arg(format!(
+                "label={}={}",
+                PROJECT_LABEL,
+                docker::project_name(&dir_name)
+            ));
+
+        let mut output = cmd::run_checked(cmd_wrapper)?;
+        output.stdout = serde_json::to_vec(&podman_services(&output.stdout)?)?;
+
+        Ok(output)
+    }
+}
+
+/// Turn the containers of `podman ps` into the services of `compose ps`
+fn podman_services(stdout: &[u8]) -> Result<Vec<Value>> {
+    let containers: Vec<Value> = match stdout.iter().all(u8::is_ascii_whitespace) {
+        true => Vec::new(),
+        false => serde_json::from_slice(stdout).context("Could not read podman ps")?,
+    };
+
+    Ok(containers
+        .iter()
+        .filter_map(|container| {
+            let service = container["Labels"][SERVICE_LABEL].as_str()?;
+            let state = match container["State"].as_str().unwrap_or_default() {
+                "stopped" => "exited",
+                "configured" => "created",
+                state => state,
+            };
+            // the health is only part of the status, e.g. "Up 5 minutes (healthy)"
+            let health = container["Status"]
+                .as_str()
+                .and_then(|status| status.strip_suffix(')')?.rsplit_once('('))
+                .map(|(_, health)| health)
+                .unwrap_or_default();
+            let ports = container["Ports"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .flat_map(|port| {
+                    let url = match port["host_ip"].as_str().unwrap_or_default() {
+                        "" => "0.0.0.0",
+                        url => url,
+                    };
+                    let target = port["container_port"].as_u64().unwrap_or_default();
+                    let published = port["host_port"].as_u64().unwrap_or_default();
+                    let protocol = port["protocol"].as_str().unwrap_or_default();
+                    (0..port["range"].as_u64().unwrap_or(1).max(1)).map(move |offset| {
+                        serde_json::json!({
+                            "URL": url,
+                            "TargetPort": target + offset,
+                            "PublishedPort": match published {
+                                0 => 0,
+                                published => published + offset,
+                            },
+                            "Protocol": protocol,
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Some(serde_json::json!({
+                "Service": service,
+                "Name": container["Names"][0].as_str().unwrap_or_default(),
+                "State": state,
+                "Health": health,
+                "ExitCode": container["ExitCode"].as_i64().unwrap_or_default(),
+                "Publishers": ports,
+            }))
+        })
+        .collect())
+}
+
+impl ContainerRuntime for NerdctlCompose {
+    fn program(&self) -> &str {
+        "nerdctl"
+    }
+
+    fn compose_command(&self, dir: &Path) -> CommandWrapper {
+        let mut cmd_wrapper = cmd::new("nerdctl", dir);
+        cmd_wrapper.command.arg("compose");
+        cmd_wrapper
+    }
+}
+
+/// Use the runtime configured for the app, or detect one if there is none.
+/// Only the first call has an effect.
+pub fn select(kind: Option<RuntimeKind>) {
+    RUNTIME.get_or_init(|| create(kind.unwrap_or_else(detect)));
+}
+
+pub fn get() -> &'static dyn ContainerRuntime {
+    RUNTIME.get_or_init(|| create(detect())).as_ref()
+}
+
+fn create(kind: RuntimeKind) -> Box<dyn ContainerRuntime> {
+    match kind {
+        RuntimeKind::Docker => Box::new(DockerCompose),
+        RuntimeKind::DockerCompose => Box::new(DockerComposeV1),
+        RuntimeKind::Podman => Box::new(PodmanCompose),
+        RuntimeKind::Nerdctl => Box::new(NerdctlCompose),
+    }
+}
+
+/// Pick the first runtime found on the PATH, preferring docker
+fn detect() -> RuntimeKind {
+    if on_path("docker") {
+        let has_plugin = file::cwd()
+            .map(|cwd| {
+                let mut cmd_wrapper = DockerCompose.compose_command(&cwd);
+                cmd_wrapper.ignore_output = true;
+                cmd_wrapper.ignore_error = true;
+                cmd_wrapper.read_only = true;
+                cmd_wrapper.command.arg("version");
+                cmd::run(cmd_wrapper).is_ok_and(|output| output.status.success())
+            })
+            .unwrap_or(false);
+
+        if !has_plugin && on_path("docker-compose") {
+            return RuntimeKind::DockerCompose;
+        }
+
+        return RuntimeKind::Docker;
+    }
+
+    [RuntimeKind::Podman, RuntimeKind::Nerdctl]
+        .into_iter()
+        .find(|kind| on_path(create(*kind).program()))
+        .unwrap_or(RuntimeKind::Docker)
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Add the arguments for a compose subcommand and run it
+fn run_compose(mut cmd_wrapper: CommandWrapper, subcommand: ComposeCmd) -> Result<Output> {
+    let is_container_command = matches!(subcommand, ComposeCmd::Run(..) | ComposeCmd::Exec(..));
+    let cmd = &mut cmd_wrapper.command;
+
+    match subcommand {
+        ComposeCmd::Up(services, files) => {
+            add_files(cmd, files);
+            cmd.args(["up", "-d"]).args(services)
+        }
+        ComposeCmd::Down(files) => {
+            add_files(cmd, files);
+            cmd.arg("down")
+        }
+        ComposeCmd::Destroy(files) => {
+            add_files(cmd, files);
+            cmd.args(["down", "--volumes", "--remove-orphans"])
+        }
+        ComposeCmd::Stop(services, files) => {
+            add_files(cmd, files);
+            cmd.arg("stop").args(services)
+        }
+        ComposeCmd::Start(services, files) => {
+            add_files(cmd, files);
+            cmd.arg("start").args(services)
+        }
+        ComposeCmd::Rm(services, files) => {
+            add_files(cmd, files);
+            cmd.args(["rm", "--force"]).args(services)
+        }
+        ComposeCmd::Config(files) => {
+            cmd_wrapper.ignore_output = true;
+            cmd_wrapper.read_only = true;
+            add_files(cmd, files);
+            cmd.args(["config", "--services"])
+        }
+        ComposeCmd::List(files) => {
+            cmd_wrapper.ignore_output = true;
+            cmd_wrapper.read_only = true;
+            add_files(cmd, files);
+            cmd.args(["ps", "--all", "--format", "json"])
+        }
+        ComposeCmd::Run(service, files, command_string) => {
+            add_files(cmd, files);
+            cmd.args(["run", "-T", "--rm", "--no-deps"])
+                .arg(service)
+                .args(command_string)
+        }
+        ComposeCmd::Exec(service, files, command_string) => {
+            add_files(cmd, files);
+            cmd.args(["exec", "-T"]).arg(service).args(command_string)
+        }
+        ComposeCmd::Logs(service, files, options) => {
+            cmd_wrapper.read_only = true;
+            add_files(cmd, files);
+            cmd.args(["logs", "--no-color", "--no-log-prefix"]);
+            if options.follow {
+                cmd.arg("--follow");
+            }
+            if let Some(since) = options.since {
+                cmd.args(["--since", &since]);
+            }
+            if let Some(tail) = options.tail {
+                cmd.args(["--tail", &tail]);
+            }
+            cmd.arg(service)
+        }
+    };
+
+    // the exit status of run and exec belongs to the command in the container
+    match is_container_command {
+        true => cmd::run(cmd_wrapper),
+        false => cmd::run_checked(cmd_wrapper),
+    }
+}
+
+/// Run tar in a throwaway container that has the volume and the directory
+/// of the archive mounted. A restore extracts the whole archive before it
+/// empties the volume, so a missing or broken archive leaves it untouched.
+fn add_tar(cmd: &mut Command, volume: &str, archive: &Path, save: bool) {
+    let dir = archive.parent().unwrap_or(Path::new("/"));
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (volume_mode, backup_mode) = match save {
+        true => (",readonly", ""),
+        false => ("", ",readonly"),
+    };
+
+    cmd.args(["run", "--rm", "--mount"])
+        .arg(format!(
+            "type=volume,src={},dst=/volume{}",
+            volume, volume_mode
+        ))
+        .arg("--mount")
+        .arg(format!(
+            "type=bind,src={},dst=/backup{}",
+            dir.display(),
+            backup_mode
+        ))
+        .arg(HELPER_IMAGE);
+
+    match save {
+        true => cmd.args([
+            "tar",
+            "czf",
+            &format!("/backup/{}", name),
+            "-C",
+            "/volume",
+            ".",
+        ]),
+        false => cmd.args([
+            "sh",
+            "-c",
+            &format!(
+                "set -e; mkdir /restore; tar xzf /backup/{} -C /restore; \
+                 find /volume -mindepth 1 -delete; cp -a /restore/. /volume/",
+                name
+            ),
+        ]),
+    };
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Create,
+    Remove,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Create => write!(f, "create"),
+            Action::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+/// Run a create or remove command, treating a network or volume that
+/// already exists or is already gone as success
+fn run_idempotent(cmd_wrapper: CommandWrapper, action: Action) -> Result<Output> {
+    let command = cmd::describe(&cmd_wrapper.command);
+    let output = cmd::run(cmd_wrapper)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let done = match action {
+        Action::Create => stderr.contains("already exists"),
+        Action::Remove => {
+            let stderr = stderr.to_lowercase();
+            stderr.contains("no such") || stderr.contains("not found")
+        }
+    };
+
+    match output.status.success() || done {
+        true => Ok(output),
+        false => Err(DocatError::CommandFailed {
+            command,
+            code: output.status.code(),
+        })
+        .context(stderr.trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{self, Health, Status};
+
+    /// Trimmed output of `podman ps --all --format json` for a project run
+    /// with podman-compose
+    const PODMAN_PS: &str = r#"[
+      {
+        "ExitCode": 0,
+        "Id": "5c1a",
+        "Labels": {
+          "com.docker.compose.project": "shop",
+          "com.docker.compose.service": "db",
+          "io.podman.compose.project": "shop"
+        },
+        "Names": ["shop_db_1"],
+        "Ports": [
+          {"host_ip": "", "container_port": 5432, "host_port": 15432, "range": 1, "protocol": "tcp"}
+        ],
+        "State": "running",
+        "Status": "Up 5 minutes (healthy)"
+      },
+      {
+        "ExitCode": 137,
+        "Id": "9e2b",
+        "Labels": {
+          "com.docker.compose.project": "shop",
+          "com.docker.compose.service": "worker"
+        },
+        "Names": ["shop_worker_1"],
+        "Ports": null,
+        "State": "exited",
+        "Status": "Exited (137) 2 minutes ago"
+      },
+      {
+        "ExitCode": 0,
+        "Id": "0f3c",
+        "Labels": {"io.podman.pod": "infra"},
+        "Names": ["infra"],
+        "Ports": null,
+        "State": "running",
+        "Status": "Up 5 minutes"
+      }
+    ]"#;
+
+    #[test]
+    fn podman_containers_are_read_as_services() {
+        let stdout = serde_json::to_vec(&podman_services(PODMAN_PS.as_bytes()).unwrap()).unwrap();
+        let services = service::parse_list(&stdout);
+
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].name, "db");
+        assert_eq!(services[0].container, "shop_db_1");
+        assert_eq!(services[0].status, Status::Running);
+        assert_eq!(services[0].health, Health::Healthy);
+        assert_eq!(services[0].ports(), "0.0.0.0:15432->5432/tcp");
+
+        assert_eq!(services[1].name, "worker");
+        assert_eq!(services[1].status, Status::Exited);
+        assert_eq!(services[1].health, Health::None);
+        assert_eq!(services[1].state(), "exited (137)");
+        assert!(services[1].ports.is_empty());
+    }
+
+    #[test]
+    fn no_podman_containers_are_no_services() {
+        assert!(podman_services(b"").unwrap().is_empty());
+        assert!(podman_services(b"[]\n").unwrap().is_empty());
+    }
+}
@@ -1,25 +1,181 @@
+use crate::git::Checkout;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use serde_with::{serde_as, DefaultOnNull};
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::PathBuf;
 
+#[serde_as]
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Service {
     #[serde(rename(deserialize = "Service"))]
     pub name: String,
+    #[serde(
+        rename(deserialize = "Name"),
+        default = "String::new",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub container: String,
     #[serde(rename(deserialize = "State"))]
     pub status: Status,
+    #[serde(
+        rename(deserialize = "Health"),
+        default = "Health::default",
+        skip_serializing_if = "Health::is_none"
+    )]
+    pub health: Health,
+    #[serde(
+        rename(deserialize = "ExitCode"),
+        default = "i32::default",
+        skip_serializing_if = "exit_code_is_zero"
+    )]
+    pub exit_code: i32,
+    #[serde_as(deserialize_as = "DefaultOnNull")]
+    #[serde(
+        rename(deserialize = "Publishers"),
+        default = "Vec::new",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub ports: Vec<Port>,
 }
 
+impl Service {
+    /// A service of the compose file that has no container
+    pub fn down(name: String) -> Self {
+        Service {
+            name,
+            container: String::new(),
+            status: Status::Down,
+            health: Health::None,
+            exit_code: 0,
+            ports: Vec::new(),
+        }
+    }
+
+    /// Shown in the status column, e.g. `exited (1)` or `running (unhealthy)`
+    pub fn state(&self) -> String {
+        match (&self.status, &self.health) {
+            (Status::Exited, _) => format!("{} ({})", self.status, self.exit_code),
+            (_, Health::None) => self.status.to_string(),
+            (status, health) => format!("{} ({})", status, health),
+        }
+    }
+
+    pub fn ports(&self) -> String {
+        self.ports
+            .iter()
+            .map(Port::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn exit_code_is_zero(code: &i32) -> bool {
+    *code == 0
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Port {
+    #[serde(
+        rename(deserialize = "URL"),
+        default = "String::new",
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub url: String,
+    #[serde(rename(deserialize = "TargetPort"))]
+    pub target: u16,
+    #[serde(rename(deserialize = "PublishedPort"), default = "u16::default")]
+    pub published: u16,
+    #[serde(rename(deserialize = "Protocol"), default = "String::new")]
+    pub protocol: String,
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.published {
+            0 => write!(f, "{}/{}", self.target, self.protocol),
+            published => write!(
+                f,
+                "{}:{}->{}/{}",
+                self.url, published, self.target, self.protocol
+            ),
+        }
+    }
+}
+
+/// Everything `docat status` reports about a project
 #[derive(Serialize, PartialEq, Debug, Clone)]
+pub struct ProjectStatus {
+    pub name: String,
+    pub dir: PathBuf,
+    pub git: String,
+    /// Only filled in when asked for with `--git`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkout: Option<Checkout>,
+    pub services: Vec<Service>,
+    /// Why the services could not be listed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Parse the output of `compose ps --format json`, which is either a json
+/// array or one json object per line depending on the compose version. A
+/// service that cannot be read is reported and left out.
+pub fn parse_list(stdout: &[u8]) -> Vec<Service> {
+    let entries = match serde_json::from_slice(stdout) {
+        Ok(Value::Array(entries)) => entries,
+        _ => stdout
+            .split(|byte| *byte == b'\n')
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect(),
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            serde_json::from_value(entry)
+                .map_err(|err| eprintln!("Could not read a service from compose: {}", err))
+                .ok()
+        })
+        .collect()
+}
+
+#[derive(Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
 pub enum Status {
-    Up,
+    Running,
+    Restarting,
+    Paused,
+    Created,
+    Exited,
+    Removing,
+    Dead,
+    /// No container exists for the service
     Down,
 }
 
+impl Status {
+    /// Whether `compose up` has to be run to get the service going again.
+    /// Restarting and paused containers are left alone.
+    pub fn is_down(&self) -> bool {
+        matches!(
+            self,
+            Status::Created | Status::Exited | Status::Dead | Status::Down
+        )
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            Status::Up => write!(f, "up  "),
+            Status::Running => write!(f, "running"),
+            Status::Restarting => write!(f, "restarting"),
+            Status::Paused => write!(f, "paused"),
+            Status::Created => write!(f, "created"),
+            Status::Exited => write!(f, "exited"),
+            Status::Removing => write!(f, "removing"),
+            Status::Dead => write!(f, "dead"),
             Status::Down => write!(f, "down"),
         }
     }
@@ -32,9 +188,59 @@ impl<'de> Deserialize<'de> for Status {
     {
         let string = String::deserialize(deserializer)?.to_lowercase();
         let state = match string.as_str() {
-            "running" => Status::Up,
+            "running" => Status::Running,
+            "restarting" => Status::Restarting,
+            "paused" => Status::Paused,
+            "created" => Status::Created,
+            "exited" => Status::Exited,
+            "removing" => Status::Removing,
+            "dead" => Status::Dead,
             _ => Status::Down,
         };
         Ok(state)
     }
 }
+
+#[derive(Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// The service has no health check
+    #[default]
+    None,
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl Health {
+    pub fn is_none(&self) -> bool {
+        *self == Health::None
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::None => write!(f, ""),
+            Health::Starting => write!(f, "starting"),
+            Health::Healthy => write!(f, "healthy"),
+            Health::Unhealthy => write!(f, "unhealthy"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Health {
+    fn deserialize<D>(deserializer: D) -> anyhow::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?.to_lowercase();
+        let health = match string.as_str() {
+            "starting" => Health::Starting,
+            "healthy" => Health::Healthy,
+            "unhealthy" => Health::Unhealthy,
+            _ => Health::None,
+        };
+        Ok(health)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{file, ProjectDirName};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILENAME: &str = "snapshot.yml";
+
+/// The volumes in a snapshot, by the project they belong to
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Snapshot {
+    pub projects: BTreeMap<ProjectDirName, Vec<String>>,
+}
+
+impl Snapshot {
+    pub fn volume_count(&self) -> usize {
+        self.projects.values().map(Vec::len).sum()
+    }
+}
+
+/// Snapshots are kept in `~/.docat/snapshots/<app>/<name>`
+pub fn path(app_name: &str, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid snapshot name {}", name);
+    }
+
+    Ok(file::snapshots_path()?.join(app_name).join(name))
+}
+
+/// The tarball a volume is archived to
+pub fn archive(dir: &Path, volume: &str) -> PathBuf {
+    dir.join(format!("{}.tar.gz", volume))
+}
+
+pub fn load(app_name: &str, name: &str) -> Result<Snapshot> {
+    let manifest = path(app_name, name)?.join(MANIFEST_FILENAME);
+    let yaml = fs::read_to_string(&manifest)
+        .with_context(|| format!("Could not find snapshot {}", name))?;
+
+    Ok(serde_yaml::from_str(&yaml)?)
+}
+
+pub fn save(dir: &Path, snapshot: &Snapshot) -> Result<()> {
+    fs::write(
+        dir.join(MANIFEST_FILENAME),
+        serde_yaml::to_string(snapshot)?,
+    )?;
+
+    Ok(())
+}
+
+/// Every snapshot of the app by name
+pub fn list(app_name: &str) -> Result<BTreeMap<String, Snapshot>> {
+    let dir = file::snapshots_path()?.join(app_name);
+    if !dir.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| dir.join(name).join(MANIFEST_FILENAME).exists())
+        .map(|name| load(app_name, &name).map(|snapshot| (name, snapshot)))
+        .collect()
+}
@@ -0,0 +1,53 @@
+//! Runs docat against a fake docker that fails any `exec`, so no container
+//! engine is needed
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const FAKE_DOCKER: &str = "#!/bin/sh
+case \"$*\" in
+  *' exec '*) exit 3 ;;
+esac
+exit 0
+";
+
+/// A workspace with an install project and a db project, whose settings
+/// are the `db` lines of yaml
+pub fn workspace(name: &str, db: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("docat-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+
+    for dir in ["bin", "home", "work/infra", "work/db"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    let docker = root.join("bin/docker");
+    fs::write(&docker, FAKE_DOCKER).unwrap();
+    fs::set_permissions(&docker, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let config = format!(
+        "shop:\n  projects:\n    infra:\n      is_install: true\n    db:\n{}",
+        db
+    );
+    fs::write(root.join("work/infra/docat.yml"), config).unwrap();
+
+    root
+}
+
+pub fn docat(root: &Path, args: &[&str]) -> Output {
+    let path = format!(
+        "{}:{}",
+        root.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    Command::new(env!("CARGO_BIN_EXE_docat"))
+        .args(args)
+        .current_dir(root.join("work/infra"))
+        .env("HOME", root.join("home"))
+        .env("PATH", path)
+        .output()
+        .unwrap()
+}
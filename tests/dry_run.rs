@@ -0,0 +1,43 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{docat, workspace};
+use std::fs;
+
+#[test]
+fn dry_run_does_not_write_the_cached_config() {
+    let root = workspace("dry-run-fresh", "      volumes: [pgdata]\n");
+
+    let output = docat(&root, &["--dry-run", "status"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!root.join("home/.docat").exists());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn dry_run_does_not_update_the_cached_config() {
+    let root = workspace("dry-run-update", "      volumes: [pgdata]\n");
+    let cached_config = root.join("home/.docat/docat.yml");
+
+    let output = docat(&root, &["status"]);
+    assert!(output.status.success(), "{:?}", output);
+    let before = fs::read_to_string(&cached_config).unwrap();
+
+    let config = root.join("work/infra/docat.yml");
+    let changed = fs::read_to_string(&config)
+        .unwrap()
+        .replace("pgdata", "other");
+    fs::write(&config, changed).unwrap();
+
+    let output = docat(&root, &["--dry-run", "status"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(fs::read_to_string(&cached_config).unwrap(), before);
+
+    let output = docat(&root, &["status"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_ne!(fs::read_to_string(&cached_config).unwrap(), before);
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,46 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{docat, workspace};
+use std::fs;
+
+#[test]
+fn failing_exec_step_fails_the_task() {
+    let root = workspace(
+        "exec-fails",
+        "      tasks:\n        migrate:\n          - service: db\n            exec: ./migrate\n",
+    );
+
+    let output = docat(&root, &["task", "migrate"]);
+    assert_eq!(output.status.code(), Some(3));
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn passing_run_step_passes_the_task() {
+    let root = workspace(
+        "run-passes",
+        "      tasks:\n        migrate:\n          - service: db\n            run: ./migrate\n",
+    );
+
+    let output = docat(&root, &["task", "migrate"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn run_step_runs_a_command_like_a_hook() {
+    let root = workspace(
+        "run-command",
+        "      tasks:\n        touch:\n          - run: touch $NAME\n            env:\n              NAME: touched\n",
+    );
+
+    let output = docat(&root, &["task", "touch"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(root.join("work/db/touched").exists());
+
+    fs::remove_dir_all(root).unwrap();
+}